@@ -0,0 +1,68 @@
+use crate::{ cidr::{ overlaps, read_cidr_file }, i18n::tr };
+use std::{ error::Error, path::Path };
+use ipnetwork::IpNetwork;
+
+// 内置的IPv4保留地址/私有地址段（RFC 6890 等）
+static BOGONS_V4: &[&str] = &[
+    "0.0.0.0/8", // 本网络
+    "10.0.0.0/8", // RFC 1918
+    "100.64.0.0/10", // CGNAT
+    "127.0.0.0/8", // 环回地址
+    "169.254.0.0/16", // 链路本地
+    "172.16.0.0/12", // RFC 1918
+    "192.0.0.0/24", // IETF协议分配
+    "192.0.2.0/24", // 文档用 TEST-NET-1
+    "192.168.0.0/16", // RFC 1918
+    "198.18.0.0/15", // 基准测试
+    "198.51.100.0/24", // 文档用 TEST-NET-2
+    "203.0.113.0/24", // 文档用 TEST-NET-3
+    "224.0.0.0/4", // 组播
+    "240.0.0.0/4", // 保留地址，包含广播地址
+];
+
+// 内置的IPv6保留地址/私有地址段
+static BOGONS_V6: &[&str] = &[
+    "::/8", // 包含未指定地址、环回地址、IPv4映射地址
+    "100::/64", // 丢弃前缀
+    "2001:2::/48", // 基准测试
+    "2001:10::/28", // ORCHID
+    "2001:db8::/32", // 文档用
+    "2002::/16", // 6to4
+    "3ffe::/16", // 已回收的6bone
+    "3fff::/20", // 文档用
+    "fc00::/7", // 唯一本地地址
+    "fe80::/10", // 链路本地
+    "fec0::/10", // 站点本地(已弃用)
+    "ff00::/8", // 组播
+];
+
+/// 保留地址列表：内置列表，加上用户文件中额外指定的CIDR
+pub struct Bogons {
+    networks: Vec<IpNetwork>,
+}
+
+impl Bogons {
+    pub fn new(extra_file: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        let mut networks: Vec<IpNetwork> = BOGONS_V4.iter()
+            .chain(BOGONS_V6)
+            .map(|s| s.parse().unwrap())
+            .collect();
+        if let Some(path) = extra_file {
+            let extra = read_cidr_file(path).map_err(|e| tr!("--bogon-file 无效：{}", "Invalid --bogon-file: {}", e))?;
+            networks.extend(extra);
+        }
+        Ok(Bogons { networks })
+    }
+
+    pub fn networks(&self) -> &[IpNetwork] {
+        &self.networks
+    }
+
+    // 返回与prefix重叠的保留地址段
+    pub fn matching(&self, prefix: IpNetwork) -> Option<IpNetwork> {
+        self.networks
+            .iter()
+            .copied()
+            .find(|&bogon| overlaps(bogon, prefix))
+    }
+}
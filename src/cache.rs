@@ -0,0 +1,57 @@
+use std::{ error::Error, fs, path::PathBuf, time::{ Duration, SystemTime, UNIX_EPOCH } };
+use serde::{ Deserialize, Serialize };
+
+// 缓存条目的元数据，与响应内容分开保存
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheMeta {
+    pub url: String,
+    pub fetched_at: u64, // 抓取时间(Unix时间戳，秒)
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub meta: CacheMeta,
+    pub body: String,
+}
+
+/// 本地响应缓存，按 来源/ASN 保存原始响应内容
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    pub dir: PathBuf,
+    pub ttl: Duration,
+    pub refresh: bool, // 忽略缓存，强制重新抓取
+    pub offline: bool, // 只使用缓存，不发出网络请求
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+impl ResponseCache {
+    fn paths(&self, source: &str, asn: u32) -> (PathBuf, PathBuf) {
+        let dir = self.dir.join(source);
+        (dir.join(format!("AS{}.body", asn)), dir.join(format!("AS{}.meta.json", asn)))
+    }
+
+    pub fn load(&self, source: &str, asn: u32) -> Option<CacheEntry> {
+        let (body_path, meta_path) = self.paths(source, asn);
+        let meta = serde_json::from_str(&fs::read_to_string(meta_path).ok()?).ok()?;
+        let body = fs::read_to_string(body_path).ok()?;
+        Some(CacheEntry { meta, body })
+    }
+
+    pub fn store(&self, source: &str, asn: u32, entry: &CacheEntry) -> Result<(), Box<dyn Error>> {
+        let (body_path, meta_path) = self.paths(source, asn);
+        fs::create_dir_all(self.dir.join(source))?;
+        fs::write(body_path, &entry.body)?;
+        fs::write(meta_path, serde_json::to_string_pretty(&entry.meta)?)?;
+        Ok(())
+    }
+
+    // 缓存是否还在有效期内
+    pub fn is_fresh(&self, entry: &CacheEntry) -> bool {
+        now_secs().saturating_sub(entry.meta.fetched_at) < self.ttl.as_secs()
+    }
+}
@@ -0,0 +1,288 @@
+use crate::i18n::tr;
+use std::{ error::Error, fs, net::{ Ipv4Addr, Ipv6Addr }, path::Path };
+use ipnetwork::IpNetwork;
+
+// 读取每行一个CIDR的文本文件，忽略空行和#开头的注释
+pub fn read_cidr_file(path: &Path) -> Result<Vec<IpNetwork>, Box<dyn Error>> {
+    let content = fs::read_to_string(path).map_err(|e| {
+        tr!("无法读取文件 {}：{}", "Cannot read file {}: {}", path.display(), e)
+    })?;
+    let mut networks = Vec::new();
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        match line.parse::<IpNetwork>() {
+            Ok(network) => networks.push(network),
+            Err(e) => {
+                return Err(
+                    tr!("{} 中有无效的CIDR：{}，{}", "Invalid CIDR in {}: {}, {}", path.display(), line, e).into()
+                );
+            }
+        }
+    }
+    Ok(networks)
+}
+
+// 两个CIDR是否有重叠（同一地址族，且其中一个包含另一个）
+pub fn overlaps(a: IpNetwork, b: IpNetwork) -> bool {
+    a.is_ipv4() == b.is_ipv4() && (a.contains(b.network()) || b.contains(a.network()))
+}
+
+// 去掉主机位，例如 1.2.3.4/24 规范化为 1.2.3.0/24
+pub fn normalize(network: IpNetwork) -> IpNetwork {
+    IpNetwork::new(network.network(), network.prefix()).unwrap()
+}
+
+// 地址族的总位数
+fn max_prefix(network: IpNetwork) -> u8 {
+    if network.is_ipv4() { 32 } else { 128 }
+}
+
+// 将前缀一分为二，返回两个长度加1的子网
+fn split(network: IpNetwork) -> (IpNetwork, IpNetwork) {
+    let len = network.prefix() + 1;
+    match network {
+        IpNetwork::V4(net) => {
+            let low = u32::from(net.network());
+            let high = low | (1 << (32 - len));
+            (
+                IpNetwork::new(Ipv4Addr::from(low).into(), len).unwrap(),
+                IpNetwork::new(Ipv4Addr::from(high).into(), len).unwrap(),
+            )
+        }
+        IpNetwork::V6(net) => {
+            let low = u128::from(net.network());
+            let high = low | (1 << (128 - len));
+            (
+                IpNetwork::new(Ipv6Addr::from(low).into(), len).unwrap(),
+                IpNetwork::new(Ipv6Addr::from(high).into(), len).unwrap(),
+            )
+        }
+    }
+}
+
+// 从network中减去excludes，剩余部分用最少的CIDR表示
+pub fn subtract(network: IpNetwork, excludes: &[IpNetwork]) -> Vec<IpNetwork> {
+    let network = normalize(network);
+    let overlapping: Vec<IpNetwork> = excludes
+        .iter()
+        .copied()
+        .filter(|&e| overlaps(e, network))
+        .collect();
+
+    if overlapping.is_empty() {
+        return vec![network];
+    }
+    // 被某个排除段整个覆盖
+    if overlapping.iter().any(|e| e.prefix() <= network.prefix()) {
+        return Vec::new();
+    }
+    if network.prefix() == max_prefix(network) {
+        return Vec::new();
+    }
+
+    let (low, high) = split(network);
+    let mut result = subtract(low, &overlapping);
+    result.extend(subtract(high, &overlapping));
+    result
+}
+
+// 前缀对应的地址范围[start, end]
+fn to_range(network: IpNetwork) -> (u128, u128) {
+    let bits = max_prefix(network) as u32;
+    let start = match network {
+        IpNetwork::V4(net) => u32::from(net.network()) as u128,
+        IpNetwork::V6(net) => u128::from(net.network()),
+    };
+    (start, block_end(start, bits - network.prefix() as u32))
+}
+
+// 从start开始、主机位为host_bits的块的最后一个地址
+fn block_end(start: u128, host_bits: u32) -> u128 {
+    if host_bits >= 128 { u128::MAX } else { start + ((1u128 << host_bits) - 1) }
+}
+
+// 把地址范围[start, end]拆分成最少的CIDR
+fn range_to_cidrs(mut start: u128, end: u128, ipv6: bool) -> Vec<IpNetwork> {
+    let bits: u32 = if ipv6 { 128 } else { 32 };
+    let mut networks = Vec::new();
+    loop {
+        // 以start为起点、对齐的最大块，且不能超过end
+        let mut host_bits = start.trailing_zeros().min(bits);
+        while block_end(start, host_bits) > end {
+            host_bits -= 1;
+        }
+        let addr: std::net::IpAddr = if ipv6 {
+            Ipv6Addr::from(start).into()
+        } else {
+            Ipv4Addr::from(start as u32).into()
+        };
+        networks.push(IpNetwork::new(addr, (bits - host_bits) as u8).unwrap());
+
+        let last = block_end(start, host_bits);
+        if last >= end {
+            break;
+        }
+        start = last + 1;
+    }
+    networks
+}
+
+// 聚合前缀：合并重叠和相邻的前缀，得到覆盖相同地址的最少CIDR
+pub fn aggregate(networks: &[IpNetwork]) -> Vec<IpNetwork> {
+    let mut result = Vec::new();
+    for ipv6 in [false, true] {
+        let mut ranges: Vec<(u128, u128)> = networks
+            .iter()
+            .filter(|n| n.is_ipv6() == ipv6)
+            .map(|&n| to_range(n))
+            .collect();
+        ranges.sort();
+
+        let mut merged: Vec<(u128, u128)> = Vec::new();
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some(last) if start <= last.1.saturating_add(1) => {
+                    last.1 = last.1.max(end);
+                }
+                _ => merged.push((start, end)),
+            }
+        }
+        for (start, end) in merged {
+            result.extend(range_to_cidrs(start, end, ipv6));
+        }
+    }
+    result
+}
+
+// 前缀覆盖的地址数，先聚合避免重叠的前缀重复计算；::/0 的地址数超出u128，取u128::MAX
+pub fn address_count(networks: &[IpNetwork]) -> u128 {
+    aggregate(networks)
+        .iter()
+        .map(|n| (1u128).checked_shl((max_prefix(*n) - n.prefix()) as u32).unwrap_or(u128::MAX))
+        .fold(0, u128::saturating_add)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nets(list: &[&str]) -> Vec<IpNetwork> {
+        list.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    fn addresses(networks: &[IpNetwork]) -> u128 {
+        networks
+            .iter()
+            .map(|n| 1u128 << ((max_prefix(*n) - n.prefix()) as u32))
+            .sum()
+    }
+
+    #[test]
+    fn subtract_without_overlap() {
+        assert_eq!(subtract(nets(&["1.1.1.0/24"])[0], &nets(&["8.8.8.0/24", "2606:4700::/32"])), nets(&["1.1.1.0/24"]));
+        assert_eq!(subtract(nets(&["1.1.1.0/24"])[0], &[]), nets(&["1.1.1.0/24"]));
+        // 主机位会被去掉
+        assert_eq!(subtract(nets(&["1.1.1.9/24"])[0], &[]), nets(&["1.1.1.0/24"]));
+    }
+
+    #[test]
+    fn subtract_covering_exclude() {
+        assert!(subtract(nets(&["1.1.1.0/24"])[0], &nets(&["1.1.1.0/24"])).is_empty());
+        assert!(subtract(nets(&["1.1.1.0/24"])[0], &nets(&["1.0.0.0/8"])).is_empty());
+        assert!(subtract(nets(&["1.1.1.1/32"])[0], &nets(&["1.1.1.1/32"])).is_empty());
+    }
+
+    #[test]
+    fn subtract_splits_into_remainder() {
+        assert_eq!(subtract(nets(&["1.1.1.0/24"])[0], &nets(&["1.1.1.0/26"])), nets(&["1.1.1.64/26", "1.1.1.128/25"]));
+        assert_eq!(
+            subtract(nets(&["10.0.0.0/30"])[0], &nets(&["10.0.0.1/32", "10.0.0.2/32"])),
+            nets(&["10.0.0.0/32", "10.0.0.3/32"])
+        );
+        assert_eq!(
+            subtract(nets(&["2001:db8::/32"])[0], &nets(&["2001:db8:8000::/33"])),
+            nets(&["2001:db8::/33"])
+        );
+    }
+
+    #[test]
+    fn complement_of_a_single_prefix() {
+        let complement = subtract(nets(&["0.0.0.0/0"])[0], &nets(&["1.1.1.0/24"]));
+        assert_eq!(complement.len(), 24);
+        assert_eq!(complement[0], nets(&["0.0.0.0/8"])[0]);
+        assert_eq!(complement[23], nets(&["128.0.0.0/1"])[0]);
+        assert!(complement.iter().all(|n| !overlaps(*n, nets(&["1.1.1.0/24"])[0])));
+        assert_eq!(addresses(&complement), (1u128 << 32) - 256);
+
+        let complement = subtract(nets(&["::/0"])[0], &nets(&["2606:4700::/32"]));
+        assert_eq!(complement.len(), 32);
+        assert_eq!(addresses(&complement), u128::MAX - ((1u128 << 96) - 1));
+    }
+
+    #[test]
+    fn complement_without_private_ranges() {
+        let excludes = nets(&["1.1.1.0/24", "10.0.0.0/8", "172.16.0.0/12", "192.168.0.0/16"]);
+        let complement = subtract(nets(&["0.0.0.0/0"])[0], &excludes);
+        for exclude in &excludes {
+            assert!(complement.iter().all(|n| !overlaps(*n, *exclude)), "{}", exclude);
+        }
+        assert_eq!(addresses(&complement), (1u128 << 32) - 256 - (1 << 24) - (1 << 20) - (1 << 16));
+    }
+
+    #[test]
+    fn aggregate_adjacent_and_overlapping() {
+        assert_eq!(aggregate(&nets(&["10.0.0.128/25", "10.0.0.0/25"])), nets(&["10.0.0.0/24"]));
+        assert_eq!(aggregate(&nets(&["10.0.0.0/24", "10.0.0.64/26", "10.0.0.0/24"])), nets(&["10.0.0.0/24"]));
+        assert_eq!(
+            aggregate(&nets(&["10.0.0.0/26", "10.0.0.32/27", "10.0.0.64/26", "10.0.0.128/25"])),
+            nets(&["10.0.0.0/24"])
+        );
+        // 相邻但不对齐，合并后的范围仍需要两个CIDR
+        assert_eq!(aggregate(&nets(&["10.0.2.0/24", "10.0.1.0/24"])), nets(&["10.0.1.0/24", "10.0.2.0/24"]));
+        assert_eq!(
+            aggregate(&nets(&["10.0.1.0/24", "10.0.2.0/23", "10.0.4.0/22"])),
+            nets(&["10.0.1.0/24", "10.0.2.0/23", "10.0.4.0/22"])
+        );
+        // 不相邻的保持不变
+        assert_eq!(aggregate(&nets(&["10.0.0.0/24", "10.0.2.0/24"])), nets(&["10.0.0.0/24", "10.0.2.0/24"]));
+        assert!(aggregate(&[]).is_empty());
+    }
+
+    #[test]
+    fn aggregate_whole_address_space() {
+        assert_eq!(aggregate(&nets(&["0.0.0.0/0", "1.1.1.0/24"])), nets(&["0.0.0.0/0"]));
+        assert_eq!(aggregate(&nets(&["128.0.0.0/1", "0.0.0.0/1"])), nets(&["0.0.0.0/0"]));
+        assert_eq!(aggregate(&nets(&["::/0", "2606:4700::/32"])), nets(&["::/0"]));
+        assert_eq!(aggregate(&nets(&["8000::/1", "::/1"])), nets(&["::/0"]));
+        // 地址空间的最后一个地址
+        assert_eq!(
+            aggregate(&nets(&["255.255.255.255/32", "255.255.255.254/32"])),
+            nets(&["255.255.255.254/31"])
+        );
+        assert_eq!(
+            aggregate(&nets(&["ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff/128", "ffff:ffff:ffff:ffff:ffff:ffff:ffff:fffe/128"])),
+            nets(&["ffff:ffff:ffff:ffff:ffff:ffff:ffff:fffe/127"])
+        );
+    }
+
+    #[test]
+    fn aggregate_mixed_families() {
+        // IPv4和IPv6分别聚合，不会因为数值相同而合并，IPv4在前
+        assert_eq!(
+            aggregate(&nets(&["::/127", "0.0.0.2/31", "0.0.0.0/31", "::2/127", "1.1.1.0/24"])),
+            nets(&["0.0.0.0/30", "1.1.1.0/24", "::/126"])
+        );
+        assert_eq!(aggregate(&nets(&["2606:4700::/32"])), nets(&["2606:4700::/32"]));
+    }
+
+    #[test]
+    fn address_count_without_overlap() {
+        assert_eq!(address_count(&nets(&["104.16.0.0/13", "1.1.1.0/24", "1.1.1.0/25"])), (1 << 19) + 256);
+        assert_eq!(address_count(&nets(&["0.0.0.0/0", "2606:4700::/32"])), (1 << 32) + (1 << 96));
+        assert_eq!(address_count(&nets(&["::/0"])), u128::MAX);
+        assert_eq!(address_count(&[]), 0);
+    }
+}
@@ -0,0 +1,108 @@
+use crate::i18n::tr;
+use std::{ collections::HashMap, error::Error, ffi::OsString, fs, path::Path };
+use clap::{ parser::ValueSource, ArgMatches, Command };
+use serde::Deserialize;
+
+// 未指定--config时，如果当前目录下有这个文件就自动加载
+pub static DEFAULT_CONFIG_FILE: &str = "download_as_cidrs.toml";
+
+/// download_as_cidrs.toml 配置文件
+///
+/// [defaults] 和 [profiles.名称] 中的键与命令行参数的长名称相同，例如：
+///
+/// ```toml
+/// [defaults]
+/// retries = 5
+///
+/// [profiles.cloudflare-edge]
+/// as = [13335, 209242]
+/// source = [1, 2]
+/// cidr-version = [4, 6]
+/// drop-bogons = true
+/// aggregate = true
+/// max-len-v4 = 24
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    // 各来源的基础URL，键为API_URL中的主机名，例如 "bgp.he.net" = "http://127.0.0.1:8080"
+    #[serde(default)]
+    pub sources: HashMap<String, String>,
+
+    // 每次运行都会使用的参数
+    #[serde(default)]
+    pub defaults: toml::Table,
+
+    // 用--profile选择的具名参数集合，覆盖[defaults]中的同名参数
+    #[serde(default)]
+    pub profiles: HashMap<String, toml::Table>,
+}
+
+impl Config {
+    pub fn load(path: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => Path::new(DEFAULT_CONFIG_FILE),
+            None => {
+                return Ok(Config::default());
+            }
+        };
+        let content = fs::read_to_string(path)?;
+        toml::from_str(&content).map_err(|e| {
+            tr!("配置文件 {} 有误：{}", "Invalid config file {}: {}", path.display(), e).into()
+        })
+    }
+
+    // 将[defaults]和所选profile转换为命令行参数；命令行中已经给出的参数不会被覆盖
+    pub fn profile_args(
+        &self,
+        profile: Option<&str>,
+        command: &Command,
+        matches: &ArgMatches
+    ) -> Result<Vec<OsString>, Box<dyn Error>> {
+        let mut table = self.defaults.clone();
+        if let Some(name) = profile {
+            let selected = self.profiles
+                .get(name)
+                .ok_or_else(|| tr!("配置文件中没有名为 {} 的profile", "No profile named {} in the config file", name))?;
+            table.extend(selected.clone());
+        }
+
+        let mut args = Vec::new();
+        for (key, value) in &table {
+            let arg = command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(key.as_str()))
+                .filter(|arg| !matches!(arg.get_long(), Some("config" | "profile")))
+                .ok_or_else(|| tr!("配置文件中有未知的参数：{}", "Unknown argument in the config file: {}", key))?;
+            if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+                continue;
+            }
+
+            let flag = format!("--{}", key);
+            let values = match value {
+                toml::Value::Array(values) => values.clone(),
+                value => vec![value.clone()],
+            };
+            for value in values {
+                match value {
+                    // 开关类参数：true时加上该参数，false时忽略
+                    toml::Value::Boolean(enabled) if !arg.get_action().takes_values() => {
+                        if enabled {
+                            args.push(OsString::from(&flag));
+                        }
+                    }
+                    toml::Value::String(s) => {
+                        args.push(OsString::from(&flag));
+                        args.push(OsString::from(s));
+                    }
+                    value => {
+                        args.push(OsString::from(&flag));
+                        args.push(OsString::from(value.to_string()));
+                    }
+                }
+            }
+        }
+        Ok(args)
+    }
+}
@@ -0,0 +1,263 @@
+use crate::{
+    cache::{ now_secs, CacheEntry, CacheMeta, ResponseCache },
+    i18n::tr,
+    metrics::Metrics,
+    ratelimit::HostLimiter,
+    Args,
+    CLIENT_USER_AGENT,
+};
+use std::{ error::Error, fs, sync::Arc, time::{ Duration, SystemTime } };
+use rand::Rng;
+use tokio::sync::SemaphorePermit;
+use tracing::{ info, warn };
+use reqwest::{
+    header::{
+        HeaderMap,
+        HeaderName,
+        HeaderValue,
+        ETAG,
+        IF_MODIFIED_SINCE,
+        IF_NONE_MATCH,
+        LAST_MODIFIED,
+        RETRY_AFTER,
+    },
+    Certificate,
+    Client,
+    Proxy,
+    RequestBuilder,
+    Response,
+    StatusCode,
+};
+
+// 根据命令行参数创建HTTP客户端，所有来源、所有ASN共用同一个客户端
+pub fn build_client(args: &Args) -> Result<Client, Box<dyn Error>> {
+    let mut builder = Client::builder()
+        .user_agent(args.user_agent.as_deref().unwrap_or(CLIENT_USER_AGENT))
+        .timeout(Duration::from_secs(args.timeout))
+        .connect_timeout(Duration::from_secs(args.connect_timeout));
+
+    // 代理，支持 http://、https://、socks5://、socks5h://
+    if let Some(proxy) = &args.proxy {
+        builder = builder.proxy(Proxy::all(proxy)?);
+    }
+
+    // 额外信任的CA证书(PEM格式，可以包含多个证书)
+    if let Some(path) = &args.ca_cert {
+        for cert in Certificate::from_pem_bundle(&fs::read(path)?)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    // 额外的请求头，格式为 "名称: 值"
+    let mut headers = HeaderMap::new();
+    for header in &args.header {
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| {
+                tr!("无效的请求头：{}，格式应为 \"名称: 值\"", "Invalid header: {}, expected \"Name: value\"", header)
+            })?;
+        headers.insert(HeaderName::try_from(name.trim())?, HeaderValue::try_from(value.trim())?);
+    }
+
+    Ok(builder.default_headers(headers).build()?)
+}
+
+// 重试前最长的等待时间；服务器要求的Retry-After超过它时不再重试
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// 网络请求失败时的重试策略，3个来源共用
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    // 第attempt次重试前的等待时间：指数退避，再加上随机抖动(delay/2 ~ delay)
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self.base_delay.saturating_mul(2u32.saturating_pow(attempt));
+        let delay = delay.min(MAX_RETRY_DELAY);
+        let millis = delay.as_millis() as u64;
+        Duration::from_millis(rand::thread_rng().gen_range(millis / 2..=millis.max(1)))
+    }
+}
+
+// 是否是值得重试的状态码：429 或 5xx
+fn is_transient(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+// 解析 Retry-After 响应头，支持秒数和HTTP日期两种格式
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+// 发送请求，遇到网络错误、429或5xx时按照重试策略重试；重试次数用完后返回最后一次的结果。
+// 每次请求(包括重试)都要先经过该网站的限速器，完成后调用observe(网络错误时为None)。
+// 连接名额和响应一起返回，调用方读完响应内容后再释放
+pub async fn send_with_retry<'a>(
+    request: RequestBuilder,
+    policy: &RetryPolicy,
+    limiter: &'a HostLimiter,
+    observe: impl Fn(Option<StatusCode>)
+) -> Result<(Response, SemaphorePermit<'a>), Box<dyn Error>> {
+    let mut attempt = 0;
+    loop {
+        let current = request.try_clone().ok_or_else(|| tr!("该请求无法重试", "The request cannot be retried"))?;
+        let permit = limiter.acquire().await;
+        let result = current.send().await;
+        observe(result.as_ref().ok().map(|r| r.status()));
+        let delay = match result {
+            Ok(response) if is_transient(response.status()) && attempt < policy.retries => {
+                let delay = match retry_after(&response) {
+                    // 等待时间太长(例如 Retry-After: 86400)时直接返回该响应，不让任务卡住
+                    Some(delay) if delay > MAX_RETRY_DELAY => {
+                        warn!(
+                            "{}",
+                            tr!(
+                                "{} 返回状态码 {}，要求{}秒后重试，超过{}秒，不再重试",
+                                "{} returned status {} with Retry-After of {}s, more than {}s, giving up",
+                                response.url(),
+                                response.status(),
+                                delay.as_secs(),
+                                MAX_RETRY_DELAY.as_secs()
+                            )
+                        );
+                        return Ok((response, permit));
+                    }
+                    Some(delay) => delay,
+                    None => policy.backoff(attempt),
+                };
+                warn!(
+                    "{}",
+                    tr!(
+                        "{} 返回状态码 {}，{:.1}秒后重试({}/{})",
+                        "{} returned status {}, retrying in {:.1}s ({}/{})",
+                        response.url(),
+                        response.status(),
+                        delay.as_secs_f64(),
+                        attempt + 1,
+                        policy.retries
+                    )
+                );
+                delay
+            }
+            Ok(response) => {
+                return Ok((response, permit));
+            }
+            Err(e) if attempt < policy.retries => {
+                let delay = policy.backoff(attempt);
+                warn!(
+                    "{}",
+                    tr!(
+                        "网络请求出错：{}，{:.1}秒后重试({}/{})",
+                        "Request error: {}, retrying in {:.1}s ({}/{})",
+                        e,
+                        delay.as_secs_f64(),
+                        attempt + 1,
+                        policy.retries
+                    )
+                );
+                delay
+            }
+            Err(e) => {
+                return Err(e.into());
+            }
+        };
+        // 等待重试时不占用连接名额
+        drop(permit);
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// 抓取到的响应：状态码和响应内容(可能来自缓存)
+pub struct Fetched {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+/// 发出请求要用到的客户端、重试策略、限速器、缓存和指标，所有ASN的下载任务共用
+pub struct Fetcher {
+    pub client: Client,
+    pub retry: RetryPolicy,
+    pub limiter: HostLimiter,
+    pub cache: Option<ResponseCache>,
+    pub metrics: Arc<Metrics>,
+}
+
+impl Fetcher {
+    // 抓取source网站上asn的页面；启用缓存时，有效期内直接用缓存，过期后用 If-None-Match/If-Modified-Since 重新验证
+    pub async fn fetch(&self, source: &str, asn: u32, url: &str) -> Result<Fetched, Box<dyn Error>> {
+        let cached = self.cache
+            .as_ref()
+            .filter(|cache| !cache.refresh)
+            .and_then(|cache| cache.load(source, asn));
+
+        if let (Some(cache), Some(entry)) = (&self.cache, &cached) {
+            if cache.offline || cache.is_fresh(entry) {
+                info!(
+                    "{}",
+                    tr!("使用缓存：{}（抓取于 {}）", "Using cache: {} (fetched at {})", url, entry.meta.fetched_at)
+                );
+                return Ok(Fetched { status: StatusCode::OK, body: entry.body.clone() });
+            }
+        }
+        if self.cache.as_ref().is_some_and(|cache| cache.offline) {
+            return Err(tr!("离线模式下没有 {} AS{} 的缓存", "No cache for {} AS{} in offline mode", source, asn).into());
+        }
+
+        let mut request = self.client.get(url);
+        if let Some(entry) = &cached {
+            if let Some(etag) = &entry.meta.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &entry.meta.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        // 读完响应内容之前一直占用连接名额
+        let (response, _permit) = send_with_retry(request, &self.retry, &self.limiter, |status| {
+            self.metrics.record_attempt(source, status)
+        }).await?;
+        let status = response.status();
+
+        // 内容没有变化，刷新缓存的抓取时间后继续使用
+        if status == StatusCode::NOT_MODIFIED {
+            if let (Some(cache), Some(mut entry)) = (&self.cache, cached) {
+                info!("{}", tr!("内容未变化，使用缓存：{}", "Not modified, using cache: {}", url));
+                entry.meta.fetched_at = now_secs();
+                cache.store(source, asn, &entry)?;
+                return Ok(Fetched { status: StatusCode::OK, body: entry.body });
+            }
+        }
+
+        let header = |name| {
+            response.headers()
+                .get(name)
+                .and_then(|v: &HeaderValue| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        let meta = CacheMeta {
+            url: url.to_string(),
+            fetched_at: now_secs(),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+        let body = response.text().await?;
+
+        if status.is_success() {
+            if let Some(cache) = &self.cache {
+                let entry = CacheEntry { meta, body };
+                cache.store(source, asn, &entry)?;
+                return Ok(Fetched { status, body: entry.body });
+            }
+        }
+        Ok(Fetched { status, body })
+    }
+}
@@ -0,0 +1,248 @@
+use std::{ ffi::OsString, sync::atomic::{ AtomicBool, Ordering } };
+use clap::{ Command, ValueEnum };
+
+/// 界面语言
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Lang {
+    /// 中文
+    Zh,
+    /// English
+    En,
+}
+
+/// csv文件的表头
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HeaderMode {
+    /// 按--lang翻译的列名
+    Localized,
+    /// 固定的英文列名，例如 prefix,country_code，便于程序读取
+    Machine,
+}
+
+static ENGLISH: AtomicBool = AtomicBool::new(false);
+
+pub fn set_lang(lang: Lang) {
+    ENGLISH.store(lang == Lang::En, Ordering::Relaxed);
+}
+
+pub fn is_english() -> bool {
+    ENGLISH.load(Ordering::Relaxed)
+}
+
+/// 按当前语言选择中文或英文的消息，参数与format!相同：tr!("中文{}", "English {}", x)
+macro_rules! tr {
+    ($zh:literal, $en:literal $(, $arg:expr)* $(,)?) => {
+        if $crate::i18n::is_english() {
+            format!($en $(, $arg)*)
+        } else {
+            format!($zh $(, $arg)*)
+        }
+    };
+}
+pub(crate) use tr;
+
+// 从语言环境变量的值判断语言，例如 zh_CN.UTF-8、en_US.UTF-8
+fn lang_from_locale(value: &str) -> Option<Lang> {
+    if value.starts_with("en") {
+        Some(Lang::En)
+    } else if value.starts_with("zh") {
+        Some(Lang::Zh)
+    } else {
+        None
+    }
+}
+
+// 在解析参数之前确定语言(帮助信息要用)：--lang 优先，其次是 LC_ALL、LC_MESSAGES、LANG，默认中文
+pub fn detect_lang(cli: &[OsString]) -> Lang {
+    let mut args = cli.iter().filter_map(|arg| arg.to_str());
+    while let Some(arg) = args.next() {
+        let value = match arg.strip_prefix("--lang") {
+            Some("") => args.next(),
+            Some(value) => value.strip_prefix('='),
+            None => None,
+        };
+        if let Some(lang) = value.and_then(|v| Lang::from_str(v, true).ok()) {
+            return lang;
+        }
+    }
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| lang_from_locale(&value))
+        .unwrap_or(Lang::Zh)
+}
+
+// 列名：(固定的英文列名, 中文, English)
+static COLUMNS: &[(&str, &str, &str)] = &[
+    ("prefix", "IP地址前缀", "Prefix"),
+    ("country_code", "国家代码", "Country code"),
+    ("country_name", "国家名称", "Country name"),
+    ("name", "名称", "Name"),
+    ("description", "描述", "Description"),
+    ("rir_name", "rir名称", "RIR name"),
+    ("rpki_state", "RPKI状态", "RPKI state"),
+];
+
+// 把固定的英文列名转换为表头中的列名
+pub fn column_label(key: &str, mode: HeaderMode) -> String {
+    if mode == HeaderMode::Machine {
+        return key.to_string();
+    }
+    COLUMNS.iter()
+        .find(|(k, _, _)| *k == key)
+        .map(|(_, zh, en)| (if is_english() { *en } else { *zh }).to_string())
+        .unwrap_or_else(|| key.to_string())
+}
+
+static ABOUT_EN: &str =
+    "Download the CIDRs of an autonomous system (ASN) from one of 3 sources: bgpview.io, bgp.he.net and bgp.tools.";
+
+// 参数的英文帮助：(参数id, 帮助)
+type ArgHelp = (&'static str, &'static str);
+
+static ARGS_EN: &[ArgHelp] = &[
+    ("asn", "ASN to download, digits only without the AS prefix; separate several ASNs with commas"),
+    ("cidr_version", "CIDR version, 4 or 6; use 4,6 for both"),
+    (
+        "api_url_index",
+        "Source to download from: 0 for bgpview.io, 1 for bgp.he.net, 2 for bgp.tools; separate several with commas",
+    ),
+    ("vrp", "Local VRP export (rpki-client / Routinator JSON or CSV) used for RPKI origin validation"),
+    ("rpki", "Filter prefixes by RPKI validation state, requires --vrp"),
+    (
+        "drop_bogons",
+        "Drop reserved ranges (private, documentation, CGNAT, multicast, ...): prefixes inside one are dropped, prefixes covering one keep the rest",
+    ),
+    ("bogon_file", "Additional bogon list file with one CIDR per line, requires --drop-bogons"),
+    ("min_len_v4", "Shortest allowed IPv4 prefix length; shorter (larger) prefixes are dropped"),
+    ("max_len_v4", "Longest allowed IPv4 prefix length, e.g. 24; longer (more specific) prefixes are dropped"),
+    ("min_len_v6", "Shortest allowed IPv6 prefix length; shorter (larger) prefixes are dropped"),
+    ("max_len_v6", "Longest allowed IPv6 prefix length, e.g. 48; longer (more specific) prefixes are dropped"),
+    (
+        "exclude",
+        "CIDRs to exclude, comma separated, or a file with one CIDR per line; overlapping prefixes are split into the remainder",
+    ),
+    ("invert", "Also write the inverted CIDR list (0.0.0.0/0 or ::/0 minus the downloaded prefixes) to *_invert.txt"),
+    ("invert_exclude_private", "Also remove reserved ranges when inverting, requires --invert"),
+    ("retries", "Maximum retries on network errors, 429 or 5xx"),
+    (
+        "retry_delay",
+        "Initial retry delay in milliseconds, growing with exponential backoff up to 60s; Retry-After from the server takes precedence, \
+         and a Retry-After over 60s is not retried",
+    ),
+    (
+        "rate",
+        "Maximum requests per second to the selected sites, at least 0.001, defaults per site; \
+         HOST=RATE only applies to that source, e.g. bgp.he.net=0.5, comma separated",
+    ),
+    (
+        "max_connections",
+        "Maximum concurrent connections to the selected sites, greater than 0, defaults per site; \
+         HOST=N only applies to that source, e.g. bgp.tools=4, comma separated",
+    ),
+    ("proxy", "Proxy URL, e.g. http://127.0.0.1:8080 or socks5://127.0.0.1:1080"),
+    ("timeout", "Timeout of a single request in seconds"),
+    ("connect_timeout", "Connect timeout in seconds"),
+    ("ca_cert", "Additional trusted CA certificates (PEM)"),
+    ("user_agent", "Custom User-Agent"),
+    ("header", "Extra request header as \"Name: value\", may be given several times"),
+    ("cache_dir", "Response cache directory; raw pages are cached per source/ASN"),
+    ("cache_ttl", "Cache lifetime in seconds; stale entries are revalidated with If-None-Match/If-Modified-Since"),
+    ("refresh", "Ignore the cache and fetch again (the result is still written to the cache)"),
+    ("offline", "Only use the cache, never send network requests"),
+    (
+        "from_file",
+        "Parse a saved page/JSON file (matching the source given by -i) instead of fetching; \"-\" reads stdin; single ASN only",
+    ),
+    (
+        "base_url",
+        "Override the base URL of the selected source, e.g. http://127.0.0.1:8080; \
+         precedence: this flag > environment variable > config [sources] > https:// plus the host name",
+    ),
+    ("aggregate", "Merge overlapping and adjacent prefixes so the txt file holds the fewest CIDRs"),
+    ("format", "Output file formats, comma separated"),
+    (
+        "output_dir",
+        "Output directory; \"-\" writes to stdout (the txt content if txt is selected, \
+         otherwise csv, which needs a single ASN, source and CIDR version)",
+    ),
+    (
+        "name_template",
+        "Output file name template without extension; placeholders: {asn}, {family}, {source}, {date}, {name}, {country}",
+    ),
+    (
+        "split_by",
+        "Split the output files, one group of files per country code; use {country} in the name template, otherwise _CODE is appended",
+    ),
+    ("country", "Only keep prefixes of these country codes, comma separated, e.g. CN,HK"),
+    (
+        "min_prefixes",
+        "Treat the parser as broken when fewer prefixes are parsed; the run fails and existing files are kept",
+    ),
+    (
+        "max_drop",
+        "Treat the parser as broken when the prefix count drops by more than this percentage; the run fails and existing files are kept",
+    ),
+    ("keep_on_shrink", "Keep the existing files when the prefix count drops by more than this percentage"),
+    ("notify", "URL notified when prefixes are added or removed, may be repeated; written as [json=|slack=|ntfy=]URL"),
+    ("metrics_file", "Write Prometheus metrics to this file after each run, for the node_exporter textfile collector"),
+    ("verbose", "More verbose logs: -v for debug (including every fetched row), -vv for trace"),
+    ("quiet", "Only log warnings and errors, -qq for errors only"),
+    ("log_format", "Log format; logs always go to stderr"),
+    ("lang", "Language of help, messages and CSV headers; defaults to LC_ALL/LC_MESSAGES/LANG, otherwise Chinese"),
+    ("csv_header", "CSV header names"),
+    ("config", "Config file path; defaults to download_as_cidrs.toml in the current directory if present"),
+    ("profile", "Use the arguments in [profiles.NAME] of the config file; command line arguments take precedence"),
+];
+
+// 子命令的英文帮助：(子命令, 说明, [(参数id, 帮助)])
+static SUBCOMMANDS_EN: &[(&str, &str, &[ArgHelp])] = &[
+    (
+        "watch",
+        "Keep running and download again on an interval; files are only rewritten and the hook only runs when content changes. Put the other arguments before watch",
+        &[
+            ("interval", "Seconds between two downloads, greater than 0"),
+            ("jitter", "Upper bound of the random seconds added to the interval, so machines don't request at the same time"),
+            ("hook", "Command run when a file changed, e.g. \"nft -f /etc/nftables.d/as.nft\" or \"systemctl reload xxx\""),
+        ],
+    ),
+    (
+        "serve",
+        "Serve prefix lists over HTTP: /as/{asn}/v4.txt, /as/{asn}/v6.txt, /as/{asn}.json, /as/{asn}/nft, /lookup/{ip}; \
+         ASNs given by --as are downloaded up front and refreshed in the background; \
+         with --allow-any-asn other ASNs are downloaded on their first request",
+        &[
+            ("listen", "Listen address"),
+            ("refresh_interval", "Seconds between background refreshes of the cached ASNs, greater than 0"),
+            ("allow_any_asn", "Allow requests for any ASN, downloaded on the first request; by default only ASNs given by --as are served"),
+        ],
+    ),
+    (
+        "stats",
+        "Address space statistics per ASN: unique addresses, prefixes per length, /24 (IPv4) or /48 and /32 (IPv6) \
+         equivalents, and the breakdown by country code",
+        &[("json", "Print JSON instead of a table")],
+    ),
+];
+
+// 英文界面时替换clap帮助信息中的中文
+pub fn localize_command(mut command: Command, lang: Lang) -> Command {
+    if lang != Lang::En {
+        return command;
+    }
+    command = command.about(ABOUT_EN);
+    for (id, help) in ARGS_EN {
+        command = command.mut_arg(*id, |arg| arg.help(*help));
+    }
+    for (name, about, args) in SUBCOMMANDS_EN {
+        command = command.mut_subcommand(*name, |mut sub| {
+            sub = sub.about(*about);
+            for (id, help) in args.iter() {
+                sub = sub.mut_arg(*id, |arg| arg.help(*help));
+            }
+            sub
+        });
+    }
+    command
+}
@@ -0,0 +1,40 @@
+use std::io::IsTerminal;
+use clap::ValueEnum;
+use tracing::Level;
+use tracing_subscriber::EnvFilter;
+
+/// 日志的输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// 便于阅读的文本
+    Text,
+    /// 每行一个JSON对象，便于日志系统收集
+    Json,
+}
+
+// 日志级别：默认info，每个-v提高一级，每个-q降低一级
+fn level(verbose: u8, quiet: u8) -> Level {
+    match (verbose as i8) - (quiet as i8) {
+        i8::MIN..=-2 => Level::ERROR,
+        -1 => Level::WARN,
+        0 => Level::INFO,
+        1 => Level::DEBUG,
+        _ => Level::TRACE,
+    }
+}
+
+// 初始化日志，所有日志都写到标准错误，不会和输出到标准输出的数据混在一起；
+// 设置了RUST_LOG环境变量时以它为准
+pub fn init(verbose: u8, quiet: u8, format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::new(format!("download_as_cidrs={}", level(verbose, quiet)))
+    });
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_target(false);
+    match format {
+        LogFormat::Text => builder.with_ansi(std::io::stderr().is_terminal()).init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
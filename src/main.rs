@@ -1,47 +1,330 @@
+mod bogon;
+mod cache;
+mod cidr;
+mod config;
+mod http;
+mod i18n;
+mod logging;
+mod metrics;
 mod models;
+mod notify;
+mod output;
+mod pipeline;
+mod ratelimit;
+mod rpki;
+mod serve;
+mod stats;
+mod watch;
 
-use crate::models::ApiResponse; // 该结构体只用于api.bgpview.io
-use std::{ error::Error, fs::File, io::Write, path::PathBuf, str };
+use crate::cache::{ now_secs, ResponseCache };
+use crate::config::Config;
+use crate::i18n::{ detect_lang, localize_command, set_lang, tr, HeaderMode, Lang };
+use crate::logging::LogFormat;
+use crate::metrics::Metrics;
+use crate::models::{ ApiResponse, HeNetPrefix, Parsed, PrefixRecord }; // ApiResponse结构体只用于api.bgpview.io
+use crate::notify::{ Notifier, PrefixChange, Sink };
+use crate::output::DEFAULT_NAME_TEMPLATE;
+use crate::http::{ build_client, Fetched, Fetcher, RetryPolicy };
+use crate::pipeline::{ OutputFormat, Pipeline, RpkiFilter, SplitBy };
+use crate::ratelimit::{ for_source, HostLimiter, PerSource };
+use crate::serve::{ serve, ServeArgs };
+use crate::stats::{ stats, StatsArgs };
+use crate::watch::{ run_hook, WatchArgs };
+use std::{
+    error::Error,
+    ffi::OsString,
+    io::Read,
+    path::{ Path, PathBuf },
+    str,
+    sync::Arc,
+    time::{ Duration, Instant },
+};
 use ipnetwork::IpNetwork;
-use csv::Writer;
 use regex::Regex;
-use reqwest::{ header::USER_AGENT, Client };
-use clap::{ error::ErrorKind, CommandFactory, Parser };
-use select::{ document::Document, predicate::{ Attr, Name, Predicate } };
+use reqwest::StatusCode;
+use clap::{
+    builder::{ PossibleValuesParser, TypedValueParser },
+    error::ErrorKind,
+    CommandFactory,
+    FromArgMatches,
+    Parser,
+    Subcommand,
+};
+use select::{ document::Document, predicate::{ Attr, Class, Name, Predicate } };
+use tracing::{ debug, error, info, warn };
 
 /// 本工具用于下载自治系统ASN的CIDR，有3个API源，分别对应bgpview.io、bgp.he.net、bgp.tools。
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    /// 指定(自治系统)ASN，输入数字，不包含AS
-    #[arg(long = "as")]
-    asn: u32,
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// 指定(自治系统)ASN，输入数字，不包含AS；多个ASN用逗号分隔，会同时下载
+    #[arg(long = "as", value_delimiter = ',')]
+    asn: Vec<u32>,
+
+    /// 指定CIDR的版本，输入4或6；两个都要时输入4,6
+    #[arg(
+        short,
+        long,
+        value_delimiter = ',',
+        default_values_t = [4],
+        value_parser = PossibleValuesParser::new(["4", "6"]).map(|s| s.parse::<u8>().unwrap())
+    )]
+    cidr_version: Vec<u8>,
+
+    /// 使用哪个API URL源下载，0为"bgpview.io"，1为"bgp.he.net", 2为"bgp.tools"；多个来源用逗号分隔
+    #[arg(
+        short = 'i',
+        long = "source",
+        value_delimiter = ',',
+        default_values_t = [0],
+        value_parser = clap::value_parser!(u8).range(0..=2)
+    )]
+    api_url_index: Vec<u8>,
+
+    /// 本地VRP导出文件(rpki-client / Routinator 的JSON或CSV格式)，用于RPKI起源验证
+    #[arg(long)]
+    vrp: Option<PathBuf>,
+
+    /// 按RPKI验证状态过滤前缀，需要同时指定--vrp
+    #[arg(long, value_enum, default_value_t = RpkiFilter::All, requires = "vrp")]
+    rpki: RpkiFilter,
+
+    /// 去掉保留地址段(私有地址、文档地址、CGNAT、组播等)：位于其中的前缀整个去掉，包含保留地址段的前缀只去掉重叠部分
+    #[arg(long)]
+    drop_bogons: bool,
+
+    /// 额外的保留地址列表文件，每行一个CIDR，需要同时指定--drop-bogons
+    #[arg(long, requires = "drop_bogons")]
+    bogon_file: Option<PathBuf>,
+
+    /// IPv4前缀的最短长度，比它更短(范围更大)的前缀将被去掉
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=32))]
+    min_len_v4: Option<u8>,
+
+    /// IPv4前缀的最长长度，比它更长(更具体)的前缀将被去掉，例如24
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=32))]
+    max_len_v4: Option<u8>,
+
+    /// IPv6前缀的最短长度，比它更短(范围更大)的前缀将被去掉
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=128))]
+    min_len_v6: Option<u8>,
+
+    /// IPv6前缀的最长长度，比它更长(更具体)的前缀将被去掉，例如48
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=128))]
+    max_len_v6: Option<u8>,
+
+    /// 要排除的CIDR，多个用逗号分隔，也可以是每行一个CIDR的文件路径；与之重叠的前缀会被拆分成剩余部分
+    #[arg(long, value_delimiter = ',')]
+    exclude: Vec<String>,
+
+    /// 额外生成反选的CIDR列表(0.0.0.0/0 或 ::/0 中除去下载到的前缀)，保存为 *_invert.txt
+    #[arg(long)]
+    invert: bool,
+
+    /// 反选时同时除去私有地址等保留地址段，需要同时指定--invert
+    #[arg(long, requires = "invert")]
+    invert_exclude_private: bool,
+
+    /// 网络错误、429或5xx时的最大重试次数
+    #[arg(long, default_value_t = 3)]
+    retries: u32,
+
+    /// 重试的初始等待时间(毫秒)，之后按指数退避递增，最长60秒；服务器返回Retry-After时以它为准，超过60秒时不再重试
+    #[arg(long, default_value_t = 1000)]
+    retry_delay: u64,
+
+    /// 对所选网站每秒最多发出的请求数，不能小于0.001，默认值见RATE_LIMITS；
+    /// 写作 主机名=值 时只作用于该来源，例如 bgp.he.net=0.5，多个用逗号分隔
+    #[arg(long, value_name = "[HOST=]RATE", value_delimiter = ',')]
+    rate: Vec<PerSource<f64>>,
+
+    /// 对所选网站的最大并发连接数，必须大于0，默认值见RATE_LIMITS；
+    /// 写作 主机名=值 时只作用于该来源，例如 bgp.tools=4，多个用逗号分隔
+    #[arg(long, value_name = "[HOST=]N", value_delimiter = ',')]
+    max_connections: Vec<PerSource<usize>>,
+
+    /// 代理地址，例如 http://127.0.0.1:8080 或 socks5://127.0.0.1:1080
+    #[arg(long)]
+    proxy: Option<String>,
+
+    /// 单个请求的超时时间(秒)
+    #[arg(long, default_value_t = 30)]
+    timeout: u64,
+
+    /// 建立连接的超时时间(秒)
+    #[arg(long, default_value_t = 10)]
+    connect_timeout: u64,
+
+    /// 额外信任的CA证书文件(PEM格式)
+    #[arg(long)]
+    ca_cert: Option<PathBuf>,
+
+    /// 自定义User-Agent，默认为CLIENT_USER_AGENT
+    #[arg(long)]
+    user_agent: Option<String>,
+
+    /// 额外的请求头，格式为 "名称: 值"，可以指定多次
+    #[arg(long)]
+    header: Vec<String>,
+
+    /// 响应缓存目录，指定后按 来源/ASN 缓存抓取到的原始页面
+    #[arg(long)]
+    cache_dir: Option<PathBuf>,
+
+    /// 缓存的有效期(秒)，过期后用 If-None-Match/If-Modified-Since 重新验证
+    #[arg(long, default_value_t = 3600)]
+    cache_ttl: u64,
+
+    /// 忽略缓存，强制重新抓取(抓取结果仍会写入缓存)
+    #[arg(long, requires = "cache_dir", conflicts_with = "offline")]
+    refresh: bool,
+
+    /// 只使用缓存，不发出网络请求
+    #[arg(long, requires = "cache_dir")]
+    offline: bool,
+
+    /// 不联网，改为解析之前保存的页面/JSON文件(与-i指定的来源对应)，"-"表示从标准输入读取；只能指定一个ASN
+    #[arg(long, conflicts_with = "cache_dir")]
+    from_file: Option<PathBuf>,
+
+    /// 替换所选来源的基础URL，例如 http://127.0.0.1:8080；
+    /// 优先级：本参数 > 环境变量(见SOURCE_URL_ENV) > 配置文件[sources] > https:// 加上API_URL中的主机名
+    #[arg(long)]
+    base_url: Option<String>,
+
+    /// 合并重叠和相邻的前缀，txt文件中输出最少的CIDR
+    #[arg(long)]
+    aggregate: bool,
+
+    /// 要输出的文件格式，多个用逗号分隔
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [OutputFormat::Csv, OutputFormat::Txt])]
+    format: Vec<OutputFormat>,
+
+    /// 输出目录，"-"表示输出到标准输出(选了txt格式时输出txt的内容，否则输出csv，此时只能有一个ASN、一个来源和一个CIDR版本)
+    #[arg(short = 'o', long, default_value = ".")]
+    output_dir: String,
+
+    /// 输出文件名模板(不含扩展名)，可用的占位符：{asn}、{family}、{source}、{date}、{name}、{country}
+    #[arg(long, default_value = DEFAULT_NAME_TEMPLATE)]
+    name_template: String,
+
+    /// 按国家代码拆分输出文件，每个国家代码一组文件；文件名模板中可用{country}，没有时在文件名后加上 _国家代码
+    #[arg(long, value_enum)]
+    split_by: Option<SplitBy>,
+
+    /// 只保留这些国家代码的前缀，多个用逗号分隔，例如 CN,HK
+    #[arg(long, value_delimiter = ',')]
+    country: Vec<String>,
+
+    /// 解析出的前缀数少于该值时认为解析器已失效，运行失败且不替换原有文件
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    min_prefixes: usize,
+
+    /// 前缀数比上次输出的文件减少超过该百分比时认为解析器已失效，运行失败且不替换原有文件
+    #[arg(long, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(0..=100))]
+    max_drop: Option<u8>,
+
+    /// 新结果的前缀数比上次输出的文件减少超过该百分比时，保留原有文件不覆盖
+    #[arg(long, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(0..=100))]
+    keep_on_shrink: Option<u8>,
+
+    /// 前缀有增减时发送通知的URL，可多次指定；写作 [json=|slack=|ntfy=]URL，默认为json格式
+    #[arg(long, value_name = "[FORMAT=]URL")]
+    notify: Vec<Sink>,
+
+    /// 每轮下载后把Prometheus指标写入该文件，供node_exporter的textfile collector读取
+    #[arg(long)]
+    metrics_file: Option<PathBuf>,
 
-    /// 指定CIDR的版本，输入4或6
-    #[arg(short, long, default_value_t = 4)]
-    cidr_version: u8,
+    /// 输出更详细的日志，-v为debug级别(包括抓取到的每一行)，-vv为trace级别
+    #[arg(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
 
-    /// 使用哪个API URL源下载，0为"bgpview.io"，1为"bgp.he.net", 2为"bgp.tools"
-    #[arg(short = 'i', default_value_t = 0)]
-    api_url_index: u8,
+    /// 只输出警告和错误，-qq只输出错误
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    quiet: u8,
+
+    /// 日志格式，日志都写到标准错误
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// 帮助、日志和csv表头的语言，默认按照LC_ALL、LC_MESSAGES、LANG环境变量，都没有时为中文
+    #[arg(long, value_enum)]
+    lang: Option<Lang>,
+
+    /// csv文件的表头
+    #[arg(long, value_enum, default_value_t = HeaderMode::Localized)]
+    csv_header: HeaderMode,
+
+    /// 配置文件路径，默认读取当前目录下的download_as_cidrs.toml(如果存在)
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// 使用配置文件中[profiles.名称]的参数，命令行中给出的参数优先
+    #[arg(long)]
+    profile: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// 常驻运行，按间隔重新下载，只在内容变化时重写文件并执行钩子命令；其他参数写在watch之前
+    Watch(WatchArgs),
+    /// 以HTTP接口提供前缀列表：/as/{asn}/v4.txt、/as/{asn}/v6.txt、/as/{asn}.json、/as/{asn}/nft、/lookup/{ip}；
+    /// --as 指定的ASN会预先下载，之后在后台定期更新；指定--allow-any-asn时其他ASN在第一次请求时下载
+    Serve(ServeArgs),
+    /// 统计ASN的地址空间：去重后的地址总数、各前缀长度的数量、相当于多少个/24(IPv4)或/48、/32(IPv6)，以及按国家代码的分布
+    Stats(StatsArgs),
 }
 
 static API_URL: &[&str] = &["api.bgpview.io", "bgp.he.net", "bgp.tools"];
+// 与API_URL一一对应的环境变量，用于替换该来源的基础URL
+static SOURCE_URL_ENV: &[&str] = &["BGPVIEW_URL", "BGP_HE_NET_URL", "BGP_TOOLS_URL"];
+// 与API_URL一一对应的默认限速：(每秒请求数, 最大并发连接数)
+static RATE_LIMITS: &[(f64, usize)] = &[
+    (2.0, 4),
+    (0.5, 1),
+    (1.0, 2),
+];
 static CLIENT_USER_AGENT: &str =
     "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36";
 
-// 文件夹不存在就创建
-fn create_folder_if_not_exists(folder_path: &str) -> Result<PathBuf, std::io::Error> {
-    let folder_path = PathBuf::from(folder_path);
+// 一个来源(API_URL中的一项)的下载配置，该来源的所有下载任务共用
+struct Source {
+    index: usize,
+    base_url: String,
+    fetcher: Fetcher,
+    saved_page: Option<String>, // --from-file 读取到的内容，代替网络抓取
+}
 
-    if folder_path.exists() {
-        Ok(folder_path)
-    } else {
-        std::fs::create_dir_all(&folder_path)?;
-        Ok(folder_path)
+// 从页面标题"AS13335 Cloudflare, Inc. - bgp.he.net"中取出AS名称，用于bgp.he.net和bgp.tools
+fn get_as_name_from_title(document: &Document) -> String {
+    let title = document
+        .find(Name("title"))
+        .next()
+        .map(|t| t.text())
+        .unwrap_or_default();
+    let title = title.split(" - ").next().unwrap_or_default().trim();
+    match title.split_once(' ') {
+        Some((asn, name)) if asn.starts_with("AS") => name.trim().to_string(),
+        _ => String::new(),
     }
 }
 
+// 按优先级确定来源的基础URL：命令行参数、环境变量、配置文件，最后是默认的 https://主机名
+fn resolve_base_url(args: &Args, config: &Config, index: usize) -> Result<String, Box<dyn Error>> {
+    let url = args.base_url
+        .clone()
+        .or_else(|| std::env::var(SOURCE_URL_ENV[index]).ok())
+        .or_else(|| config.sources.get(API_URL[index]).cloned())
+        .unwrap_or_else(|| format!("https://{}", API_URL[index]));
+    // 提前检查URL是否有效
+    reqwest::Url::parse(&url).map_err(|e| tr!("无效的基础URL：{}，{}", "Invalid base URL: {}, {}", url, e))?;
+    Ok(url.trim_end_matches('/').to_string())
+}
+
 // 该函数应用到"bgp.he.net"中
 fn get_country_code_from_gifurl(url: &str) -> Option<&str> {
     let re = Regex::new(r"([^/]+)\.").unwrap();
@@ -51,79 +334,267 @@ fn get_country_code_from_gifurl(url: &str) -> Option<&str> {
         .map(|m| m.as_str())
 }
 
+// 按当前语言生成的clap命令，帮助信息使用该语言
+fn command() -> clap::Command {
+    let lang = if i18n::is_english() { Lang::En } else { Lang::Zh };
+    localize_command(Args::command(), lang)
+}
+
+// 解析命令行参数，再用配置文件中的[defaults]和--profile指定的参数补充命令行中没有给出的参数
+fn parse_args() -> Result<(Args, Config), Box<dyn Error>> {
+    let cli: Vec<OsString> = std::env::args_os().collect();
+    set_lang(detect_lang(&cli));
+    let matches = command().try_get_matches_from(&cli)?;
+    let config = Config::load(matches.get_one::<PathBuf>("config").map(PathBuf::as_path))?;
+    let extra = config.profile_args(
+        matches.get_one::<String>("profile").map(String::as_str),
+        &command(),
+        &matches
+    )?;
+    // 补充的参数插在程序名之后，保证它们位于子命令之前
+    let mut cli = cli.into_iter();
+    let matches = command().try_get_matches_from(cli.next().into_iter().chain(extra).chain(cli))?;
+    let args = Args::from_arg_matches(&matches)?;
+    // 配置文件中也可以指定语言
+    if let Some(lang) = args.lang {
+        set_lang(lang);
+    }
+
+    if args.asn.is_empty() && !matches!(args.command, Some(Commands::Serve(_))) {
+        return Err(
+            command()
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    tr!("需要用--as或profile指定ASN", "An ASN is required, given by --as or a profile")
+                )
+                .into()
+        );
+    }
+    if args.from_file.is_some() && (args.asn.len() > 1 || args.api_url_index.len() > 1) {
+        return Err(
+            tr!("--from-file 只能和一个ASN、一个来源一起使用", "--from-file only works with a single ASN and source").into()
+        );
+    }
+    if args.output_dir == "-" && args.invert {
+        return Err(tr!("--invert 不能和 -o - 一起使用", "--invert cannot be used with -o -").into());
+    }
+    // 每个下载任务各自输出一段带表头的csv，多个任务的结果无法拼成一个csv
+    let tasks = args.asn.len() * args.api_url_index.len() * args.cidr_version.len();
+    if args.output_dir == "-" && !args.format.contains(&OutputFormat::Txt) && tasks > 1 && args.command.is_none() {
+        return Err(
+            tr!(
+                "-o - 输出csv时只能有一个ASN、一个来源和一个CIDR版本，多个请输出到目录或改用 --format txt",
+                "-o - with csv output only works with a single ASN, source and CIDR version; write to a directory or use --format txt"
+            ).into()
+        );
+    }
+    if args.output_dir == "-" && args.split_by.is_some() {
+        return Err(tr!("--split-by 不能和 -o - 一起使用", "--split-by cannot be used with -o -").into());
+    }
+    if args.name_template.contains("{country}") && args.split_by.is_none() {
+        return Err(
+            tr!(
+                "文件名模板中的 {{country}} 需要同时指定 --split-by country",
+                "{{country}} in the name template requires --split-by country"
+            ).into()
+        );
+    }
+    let daemon = matches!(args.command, Some(Commands::Watch(_) | Commands::Serve(_)));
+    if daemon && (args.output_dir == "-" || args.from_file.is_some()) {
+        return Err(
+            tr!(
+                "watch 和 serve 不能和 -o - 或 --from-file 一起使用",
+                "watch and serve cannot be used with -o - or --from-file"
+            ).into()
+        );
+    }
+    if args.base_url.is_some() && args.api_url_index.len() > 1 {
+        return Err(
+            tr!(
+                "--base-url 只能和一个来源一起使用，多个来源请用环境变量或配置文件",
+                "--base-url only works with a single source; use environment variables or the config file for several"
+            ).into()
+        );
+    }
+    Ok((args, config))
+}
+
+// 每个来源一份下载配置：客户端、重试策略、限速器和缓存，该网站的所有下载任务共用
+fn build_sources(
+    args: &Args,
+    config: &Config,
+    metrics: &Arc<Metrics>
+) -> Result<Vec<Arc<Source>>, Box<dyn Error>> {
+    let client = build_client(args)?;
+    let saved_page = match &args.from_file {
+        Some(path) => Some(read_saved_page(path)?),
+        None => None,
+    };
+
+    let mut sources = Vec::new();
+    for &index in &args.api_url_index {
+        let index = index as usize;
+        let (default_rate, default_connections) = RATE_LIMITS[index];
+        sources.push(
+            Arc::new(Source {
+                index,
+                base_url: resolve_base_url(args, config, index)?,
+                fetcher: Fetcher {
+                    client: client.clone(),
+                    retry: RetryPolicy {
+                        retries: args.retries,
+                        base_delay: Duration::from_millis(args.retry_delay),
+                    },
+                    limiter: HostLimiter::new(
+                        for_source(&args.rate, API_URL[index]).unwrap_or(default_rate),
+                        for_source(&args.max_connections, API_URL[index]).unwrap_or(default_connections)
+                    ),
+                    cache: args.cache_dir.as_ref().map(|dir| ResponseCache {
+                        dir: dir.clone(),
+                        ttl: Duration::from_secs(args.cache_ttl),
+                        refresh: args.refresh,
+                        offline: args.offline,
+                    }),
+                    metrics: metrics.clone(),
+                },
+                saved_page: saved_page.clone(),
+            })
+        );
+    }
+    Ok(sources)
+}
+
+// 一轮下载的结果
+struct RunSummary {
+    changes: Vec<PrefixChange>, // 内容有变化的下载任务
+    failed: usize,
+}
+
+// 每个来源、每个CIDR版本、每个ASN一个下载任务，全部完成后汇总结果，并写入指标文件
+async fn run_once(
+    args: &Args,
+    sources: &[Arc<Source>],
+    pipeline: &Arc<Pipeline>,
+    metrics: &Metrics
+) -> Result<RunSummary, Box<dyn Error>> {
+    let mut tasks = tokio::task::JoinSet::new();
+    for source in sources {
+        for &asn in &args.asn {
+            let (source, pipeline, versions) = (source.clone(), pipeline.clone(), args.cidr_version.clone());
+            tasks.spawn(async move {
+                let results = download_asn(&source, &versions, asn, &pipeline).await;
+                (API_URL[source.index], asn, results)
+            });
+        }
+    }
+
+    let mut summary = RunSummary { changes: Vec::new(), failed: 0 };
+    while let Some(joined) = tasks.join_next().await {
+        let (source, asn, results) = joined?;
+        for (version, result) in results {
+            match result {
+                Ok(Some(change)) => {
+                    summary.changes.push(change);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    error!("{}", tr!("AS{} v{} ({}) 下载失败：{}", "AS{} v{} ({}) failed: {}", asn, version, source, e));
+                    summary.failed += 1;
+                }
+            }
+        }
+    }
+    if let Some(path) = &args.metrics_file {
+        if let Err(e) = metrics.write(path) {
+            warn!("{}", tr!("写入指标文件 {} 失败：{}", "Failed to write metrics file {}: {}", path.display(), e));
+        }
+    }
+    Ok(summary)
+}
+
+// watch子命令：按间隔重复下载，失败时只报告，等下一轮重试
+async fn watch(
+    args: &Args,
+    watch: &WatchArgs,
+    sources: &[Arc<Source>],
+    pipeline: &Arc<Pipeline>,
+    metrics: &Metrics,
+    notifier: &Notifier
+) -> Result<(), Box<dyn Error>> {
+    loop {
+        let summary = run_once(args, sources, pipeline, metrics).await?;
+        notifier.notify(&summary.changes).await;
+        if summary.failed > 0 {
+            warn!("{}", tr!("{} 个下载任务失败", "{} download tasks failed", summary.failed));
+        }
+        if !summary.changes.is_empty() {
+            info!("{}", tr!("{} 个下载任务的内容有变化", "{} download tasks changed", summary.changes.len()));
+            if let Some(hook) = &watch.hook {
+                if let Err(e) = run_hook(hook).await {
+                    warn!("{}", e);
+                }
+            }
+        }
+
+        let delay = watch.next_delay();
+        info!("{}", tr!("{} 秒后重新下载", "Downloading again in {} seconds", delay.as_secs()));
+        tokio::time::sleep(delay).await;
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let result = Args::try_parse();
+    let result = parse_args();
     match result {
-        Ok(args) => {
-            let save_folder_path = API_URL[args.api_url_index as usize];
-            // 检查要保存到的文件夹是否存在，不存在则创建
-            match create_folder_if_not_exists(save_folder_path) {
-                Ok(_path) => {}
-                Err(e) => eprintln!("Error creating folder: {}", e),
-            }
+        Ok((args, config)) => {
+            logging::init(args.verbose, args.quiet, args.log_format);
 
-            // 输出的csv文件和txt文件
-            let output_csv = format!(
-                "{}/AS{}_v{}.csv",
-                save_folder_path,
-                args.asn,
-                args.cidr_version
-            );
-            let output_txt = format!(
-                "{}/AS{}_v{}.txt",
-                save_folder_path,
-                args.asn,
-                args.cidr_version
-            );
+            // 写入文件前的处理步骤(规范化、长度过滤、保留地址过滤、排除、RPKI验证等)
+            let metrics = Arc::new(Metrics::default());
+            if let Some(path) = &args.metrics_file {
+                metrics.restore(path);
+            }
+            let pipeline = Arc::new(Pipeline::from_args(&args, metrics.clone())?);
+            let sources = build_sources(&args, &config, &metrics)?;
+            let notifier = Notifier::new(build_client(&args)?, args.notify.clone());
 
-            // 按照不同的API_URL来源，下载asn的cidr
-            match args.api_url_index {
-                0 => {
-                    let client = Client::builder().user_agent("Mozilla/5.0").build()?;
-                    let response = client
-                        .get(format!("https://{}/asn/{}/prefixes", API_URL[0], args.asn))
-                        .send().await?;
-                    download_api_bgpview_io(response, &args, &output_csv, &output_txt).await?;
+            match &args.command {
+                Some(Commands::Watch(watch_args)) => {
+                    watch(&args, watch_args, &sources, &pipeline, &metrics, &notifier).await?;
+                }
+                Some(Commands::Serve(serve_args)) => {
+                    serve(serve_args, &args.asn, sources[0].clone(), pipeline).await?;
                 }
-                1 => {
-                    let client = reqwest::Client::new();
-                    let response = client
-                        .get(
-                            format!(
-                                "https://{}/AS{}#_prefixes{}",
-                                API_URL[1],
-                                args.asn,
-                                args.cidr_version
-                            )
-                                .trim_end_matches('4') // 如果后面的数字是4，则去掉
-                                .to_string()
-                        )
-                        .header(USER_AGENT, CLIENT_USER_AGENT)
-                        .send().await?;
-                    download_bgp_he_net(response, &args, &output_csv, &output_txt).await?;
+                Some(Commands::Stats(stats_args)) => {
+                    stats(stats_args, &args.asn, &args.cidr_version, &sources, &pipeline).await?;
                 }
-                2 => {
-                    let client = reqwest::Client::new();
-                    let response = client
-                        .get(format!("https://{}/as/{}#prefixes", API_URL[2], args.asn))
-                        .header(USER_AGENT, CLIENT_USER_AGENT)
-                        .send().await?;
-                    download_bgp_tools(response, &args, &output_csv, &output_txt).await?;
+                None => {
+                    let summary = run_once(&args, &sources, &pipeline, &metrics).await?;
+                    notifier.notify(&summary.changes).await;
+                    if summary.failed > 0 {
+                        return Err(tr!("{} 个下载任务失败", "{} download tasks failed", summary.failed).into());
+                    }
                 }
-                _ => panic!("Invalid api_url_index"),
-            };
+            }
         }
         Err(e) => {
-            if
-                e.kind() == ErrorKind::MissingRequiredArgument ||
-                e.kind() == ErrorKind::InvalidValue
-            {
-                // 如果是因为缺少必需参数或无效值导致的错误，则显示帮助信息
-                Args::command().print_help().unwrap();
-            } else {
-                // 其他类型的错误则正常打印错误信息
-                e.print().unwrap();
+            match e.downcast::<clap::Error>() {
+                Ok(e) => {
+                    if
+                        e.kind() == ErrorKind::MissingRequiredArgument ||
+                        e.kind() == ErrorKind::InvalidValue
+                    {
+                        // 如果是因为缺少必需参数或无效值导致的错误，则显示帮助信息
+                        command().print_help().unwrap();
+                    } else {
+                        // 其他类型的错误则正常打印错误信息
+                        e.print().unwrap();
+                    }
+                }
+                // 配置文件等其他错误
+                Err(e) => {
+                    return Err(e);
+                }
             }
         }
     }
@@ -131,288 +602,304 @@ async fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-async fn download_api_bgpview_io(
-    response: reqwest::Response,
-    args: &Args,
-    output_csv: &str,
-    output_txt: &str
-) -> Result<(), Box<dyn Error>> {
-    if response.status().is_success() {
-        let json: ApiResponse = response.json().await?;
-        if json.status == "ok" {
-            // 创建一个csv文件
-            let mut wtr = Writer::from_writer(File::create(output_csv)?);
-            wtr.write_record(&["IP地址前缀", "国家代码", "名称", "描述", "rir名称"])?;
-            // 创建一个txt文件
-            let mut file = File::create(output_txt).expect("Failed to create txt file");
-            // 处理数据
-            match args.cidr_version {
-                4 => {
-                    json.data.ipv4_prefixes.iter().for_each(|p| {
-                        println!(
-                            "抓取到内容：{:?}",
-                            vec![
-                                p.prefix.clone(),
-                                p.country_code.clone().unwrap_or_default(),
-                                p.description.clone().unwrap_or_default()
-                            ]
-                        );
-                        // 写入csv
-                        wtr.write_record(
-                            &[
-                                p.prefix.to_string(),
-                                p.country_code.clone().unwrap_or_default(),
-                                p.name.clone().unwrap_or_default(),
-                                p.description.clone().unwrap_or_default(),
-                                p.parent.rir_name.clone().unwrap_or_default(),
-                            ]
-                        ).expect("Failed to write to csv file");
-                        // 写入txt
-                        writeln!(file, "{}", p.prefix.to_string()).expect(
-                            "Failed to write to txt file"
-                        );
-                    });
-                }
-                6 => {
-                    json.data.ipv6_prefixes.iter().for_each(|p| {
-                        println!(
-                            "抓取到内容：{:?}",
-                            vec![
-                                p.prefix.clone(),
-                                p.country_code.clone().unwrap_or_default(),
-                                p.description.clone().unwrap_or_default()
-                            ]
-                        );
-                        // 写入csv
-                        wtr.write_record(
-                            &[
-                                p.prefix.to_string(),
-                                p.country_code.clone().unwrap_or_default(),
-                                p.name.clone().unwrap_or_default(),
-                                p.description.clone().unwrap_or_default(),
-                                p.parent.rir_name.clone().unwrap_or_default(),
-                            ]
-                        ).expect("Failed to write to csv file");
-                        // 写入txt
-                        writeln!(file, "{}", p.prefix.to_string()).expect(
-                            "Failed to write to txt file"
-                        );
-                    });
-                }
-                _ => unreachable!(),
-            }
-        } else {
-            eprintln!("获取到的数据状态不是ok，而是{}", json.status);
+// 按照不同的API_URL来源，下载一个asn的页面(只抓取一次)，分别写入每个CIDR版本的cidr；
+// 返回每个版本的结果，输出文件有变化时为前缀的增减
+async fn download_asn(
+    source: &Source,
+    versions: &[u8],
+    asn: u32,
+    pipeline: &Pipeline
+) -> Vec<(u8, Result<Option<PrefixChange>, String>)> {
+    let Some(&first) = versions.first() else {
+        return Vec::new();
+    };
+    let body = match fetch_page(source, first, asn).await {
+        Ok(body) => body,
+        Err(e) => {
+            let e = e.to_string();
+            return versions
+                .iter()
+                .map(|&version| (version, Err(e.clone())))
+                .collect();
         }
+    };
+    versions
+        .iter()
+        .map(|&version| {
+            let result = write_version(source, &body, version, asn, pipeline).map_err(|e| e.to_string());
+            (version, result)
+        })
+        .collect()
+}
+
+// 从抓取到的页面中解析出一个CIDR版本的前缀并写入文件
+fn write_version(
+    source: &Source,
+    body: &str,
+    version: u8,
+    asn: u32,
+    pipeline: &Pipeline
+) -> Result<Option<PrefixChange>, Box<dyn Error>> {
+    let parsed = parse_page(source, body, version)?;
+    let change = pipeline.write(parsed, API_URL[source.index], asn, version)?;
+    source.fetcher.metrics.record_success(API_URL[source.index], asn, version, now_secs());
+    Ok(change)
+}
+
+// 抓取一个asn的页面/JSON；同一个页面中同时包含IPv4和IPv6的前缀
+async fn fetch_page(source: &Source, version: u8, asn: u32) -> Result<String, Box<dyn Error>> {
+    let base_url = &source.base_url;
+    let url = match source.index {
+        0 => format!("{}/asn/{}/prefixes", base_url, asn),
+        1 =>
+            format!("{}/AS{}#_prefixes{}", base_url, asn, version)
+                .trim_end_matches('4') // 如果后面的数字是4，则去掉
+                .to_string(),
+        2 => format!("{}/as/{}#prefixes", base_url, asn),
+        _ => panic!("Invalid api_url_index"),
+    };
+
+    // 从保存下来的页面/JSON文件读取，或者从网络抓取
+    let fetched = match &source.saved_page {
+        Some(body) => Fetched { status: StatusCode::OK, body: body.clone() },
+        None => source.fetcher.fetch(API_URL[source.index], asn, &url).await?,
+    };
+    if !fetched.status.is_success() {
+        return Err(
+            tr!("HTTP网页请求失败，状态码是: {}", "HTTP request failed with status: {}", fetched.status).into()
+        );
+    }
+    Ok(fetched.body)
+}
+
+// 从抓取到的页面/JSON中解析出一个CIDR版本的前缀记录
+fn parse_page(source: &Source, body: &str, version: u8) -> Result<Parsed, Box<dyn Error>> {
+    let started = Instant::now();
+    let parsed = match source.index {
+        0 => parse_api_bgpview_io(body, version)?,
+        1 => parse_bgp_he_net(body, version),
+        2 => parse_bgp_tools(body, version),
+        _ => unreachable!(),
+    };
+    source.fetcher.metrics.record_parse(API_URL[source.index], started.elapsed());
+    Ok(parsed)
+}
+
+// 读取之前保存的 bgp.he.net/bgp.tools 页面或 bgpview 的JSON，路径为"-"时从标准输入读取
+fn read_saved_page(path: &Path) -> Result<String, Box<dyn Error>> {
+    if path == Path::new("-") {
+        let mut body = String::new();
+        std::io::stdin().read_to_string(&mut body)?;
+        Ok(body)
     } else {
-        eprintln!("HTTP网页请求失败，状态码是: {}", response.status());
+        Ok(std::fs::read_to_string(path)?)
     }
-    Ok(())
 }
 
-async fn download_bgp_he_net(
-    response: reqwest::Response,
-    args: &Args,
-    output_csv: &str,
-    output_txt: &str
-) -> Result<(), Box<dyn Error>> {
-    if response.status().is_success() {
-        // 创建一个csv文件
-        let mut writer = Writer::from_path(output_csv).unwrap();
-        writer.write_record(vec!["IP地址前缀", "国家代码", "国家名称", "描述"]).unwrap();
-        // 创建一个txt文件
-        let mut file = File::create(output_txt).expect("Failed to create file");
-
-        // 获取 HTML 内容为字符串
-        let content = response.text().await?;
-        // 使用 select 解析 HTML
-        let document = Document::from(content.as_str());
-
-        // 匹配对应的表格ID
-        let table_id = match args.cidr_version {
-            4 => "table_prefixes4",
-            6 => "table_prefixes6",
-            _ => panic!("Invalid version"),
+fn parse_api_bgpview_io(body: &str, version: u8) -> Result<Parsed, Box<dyn Error>> {
+    let json: ApiResponse = serde_json::from_str(body)?;
+    if json.status == "ok" {
+        // 处理数据
+        let prefixes = match version {
+            4 => &json.data.ipv4_prefixes,
+            6 => &json.data.ipv6_prefixes,
+            _ => unreachable!(),
         };
+        let mut records = Vec::new();
+        for p in prefixes {
+            debug!(
+                "{}：{:?}",
+                tr!("抓取到内容", "Fetched row"),
+                vec![
+                    p.prefix.clone(),
+                    p.country_code.clone().unwrap_or_default(),
+                    p.description.clone().unwrap_or_default()
+                ]
+            );
+            match p.prefix.parse::<IpNetwork>() {
+                Ok(prefix) =>
+                    records.push(PrefixRecord {
+                        prefix,
+                        row: vec![
+                            p.prefix.to_string(),
+                            p.country_code.clone().unwrap_or_default(),
+                            p.name.clone().unwrap_or_default(),
+                            p.description.clone().unwrap_or_default(),
+                            p.parent.rir_name.clone().unwrap_or_default()
+                        ],
+                    }),
+                Err(e) => warn!("{}", tr!("无法解析的CIDR：{}，{}", "Unparseable CIDR: {}, {}", p.prefix, e)),
+            }
+        }
+        // bgpview的前缀接口中没有AS名称，用第一个前缀的名称代替
+        let name = prefixes
+            .iter()
+            .find_map(|p| p.name.clone())
+            .unwrap_or_default();
+        Ok(Parsed {
+            header: &["prefix", "country_code", "name", "description", "rir_name"],
+            records,
+            name,
+        })
+    } else {
+        Err(tr!("获取到的数据状态不是ok，而是{}", "Response status is not ok but {}", json.status).into())
+    }
+}
 
-        println!();
+fn parse_bgp_he_net(body: &str, version: u8) -> Parsed {
+    // 使用 select 解析 HTML
+    let document = Document::from(body);
+    let name = get_as_name_from_title(&document);
 
-        // 找到表格的所有行
-        for row in document.find(Attr("id", table_id).descendant(Name("tr"))) {
-            let cells: Vec<_> = row
-                .find(Name("td"))
-                .map(|cell: select::node::Node<'_>| {
-                    // 创建一个向量，用于存储结果
-                    let mut elements = Vec::new();
+    // 匹配对应的表格ID
+    let table_id = match version {
+        4 => "table_prefixes4",
+        6 => "table_prefixes6",
+        _ => panic!("Invalid version"),
+    };
 
-                    // 查找 div.flag 下的 img 元素
-                    if
-                        let Some(div) = cell
-                            .find(Attr("class", "flag alignright floatright"))
-                            .next()
-                    {
-                        for img in div.find(Name("img")) {
-                            if let Some(src) = img.attr("src") {
-                                let substring = get_country_code_from_gifurl(src);
-                                match substring {
-                                    Some(s) => elements.push(s.to_uppercase()), // country code
-                                    None => elements.push("".to_string()),
-                                }
-                            }
-                            if let Some(title) = img.attr("title") {
-                                elements.push(title.to_string()); // country name
-                            } else {
-                                elements.push("".to_string());
-                            }
-                        }
-                    }
+    let mut records = Vec::new();
+    let Some(table) = document.find(Attr("id", table_id)).next() else {
+        return Parsed { header: HeNetPrefix::HEADER, records, name };
+    };
 
-                    // 添加 td 的文本内容
-                    let text = cell.text().trim().to_string();
-                    if !text.is_empty() {
-                        elements.push(text);
-                    } else {
-                        elements.push("".to_string());
-                    }
+    // 按表头的列名确定各列的位置，不依赖列的顺序，也不受国旗有无的影响
+    let columns: Vec<String> = table
+        .find(Name("thead").descendant(Name("th")))
+        .map(|th| th.text().trim().to_lowercase())
+        .collect();
+    let column = |name: &str| columns.iter().position(|c| c == name);
+    let Some(prefix_column) = column("prefix") else {
+        warn!(
+            "{}",
+            tr!("bgp.he.net 的表格 {} 中没有 Prefix 列，表头为：{:?}", "bgp.he.net table {} has no Prefix column, header: {:?}", table_id, columns)
+        );
+        return Parsed { header: HeNetPrefix::HEADER, records, name };
+    };
+    let description_column = column("description");
 
-                    elements // 返回当前单元格解析出的内容
-                })
-                .collect();
-            if !cells.is_empty() {
-                let one_dimensional: Vec<String> = cells.clone().into_iter().flatten().collect(); // 将二维向量转换为一维向量
-                // 判断CIDR的类型，4 或 6？
-                if let Ok(ip_network) = one_dimensional[0].parse::<IpNetwork>() {
-                    match ip_network {
-                        IpNetwork::V4(v4_network) => {
-                            if args.cidr_version == 4 {
-                                println!("抓取到内容：{:?}", one_dimensional);
-                                writer
-                                    .write_record(&one_dimensional)
-                                    .expect("Failed to write to csv file");
-                                writeln!(file, "{}", v4_network.to_string()).expect(
-                                    "Failed to write to txt file"
-                                );
-                            }
-                        }
-                        IpNetwork::V6(v6_network) => {
-                            if args.cidr_version == 6 {
-                                println!("抓取到内容：{:?}", one_dimensional);
-                                writer
-                                    .write_record(&one_dimensional)
-                                    .expect("Failed to write to csv file");
-                                writeln!(file, "{}", v6_network.to_string()).expect(
-                                    "Failed to write to txt file"
-                                );
-                            }
-                        }
-                    }
-                }
-            }
+    // 找到表格的所有行
+    for row in table.find(Name("tr")) {
+        let cells: Vec<_> = row.find(Name("td")).collect();
+        if cells.is_empty() {
+            continue; // 表头行
         }
-    } else {
-        println!("HTTP网页请求失败，状态码是: {}", response.status());
+        if cells.len() != columns.len() {
+            let text = row.text();
+            let text: Vec<&str> = text.split_whitespace().collect();
+            warn!(
+                "{}",
+                tr!(
+                    "bgp.he.net 的表格行有 {} 列，与表头的 {} 列不一致，已跳过：{}",
+                    "bgp.he.net table row has {} cells but the header has {} columns, skipped: {}",
+                    cells.len(),
+                    columns.len(),
+                    text.join(" ")
+                )
+            );
+            continue;
+        }
+        // 最后一行"Show more"等不是CIDR的行
+        let text = cells[prefix_column].text().trim().to_string();
+        let Ok(prefix) = text.parse::<IpNetwork>() else {
+            debug!("{}", tr!("不是CIDR的表格行，已跳过：{}", "Row without a CIDR, skipped: {}", text));
+            continue;
+        };
+        // 判断CIDR的类型，4 或 6？
+        if prefix.is_ipv4() != (version == 4) {
+            continue;
+        }
+
+        // 国旗图片：文件名是国家代码，title是国家名称
+        let flag = row.find(Class("flag").descendant(Name("img"))).next();
+        let entry = HeNetPrefix {
+            prefix,
+            country_code: flag
+                .and_then(|img| img.attr("src"))
+                .and_then(get_country_code_from_gifurl)
+                .map(|code| code.to_uppercase())
+                .unwrap_or_default(),
+            country_name: flag
+                .and_then(|img| img.attr("title"))
+                .unwrap_or_default()
+                .to_string(),
+            description: description_column
+                .map(|i| cells[i].text().trim().to_string())
+                .unwrap_or_default(),
+        };
+        debug!("{}：{:?}", tr!("抓取到内容", "Fetched row"), entry);
+        records.push(entry.into_record());
     }
-    Ok(())
+    Parsed { header: HeNetPrefix::HEADER, records, name }
 }
 
-async fn download_bgp_tools(
-    response: reqwest::Response,
-    args: &Args,
-    output_csv: &str,
-    output_txt: &str
-) -> Result<(), Box<dyn Error>> {
-    if response.status().is_success() {
-        // 创建一个csv文件
-        let mut writer = Writer::from_path(output_csv).unwrap();
-        writer.write_record(vec!["IP地址前缀", "国家代码", "描述"]).unwrap();
-        // 创建一个txt文件
-        let mut file = File::create(output_txt).expect("Failed to create file");
-
-        // 获取 HTML 内容为字符串
-        let content = response.text().await?;
-        // 使用 select 解析 HTML
-        let document = Document::from(content.as_str());
-
-        println!();
-
-        // 找到表格的所有行
-        for row in document.find(
-            Attr("id", "donotscrapebgptools-prefixlist-tbody").descendant(Name("tr"))
-        ) {
-            let cells: Vec<_> = row
-                .find(Name("td"))
-                .map(|cell: select::node::Node<'_>| {
-                    // 创建一个向量，用于存储结果
-                    let mut elements = Vec::new();
-
-                    // 查找 img 元素的国家代码
-                    if let Some(img) = cell.find(Name("img")).next() {
-                        // 获取第一个 img
-                        if let Some(title) = img.attr("title") {
-                            // 获取第一个 img 的 title
-                            elements.push(title.to_string());
-                        }
-                    }
+fn parse_bgp_tools(body: &str, version: u8) -> Parsed {
+    // 使用 select 解析 HTML
+    let document = Document::from(body);
 
-                    // 添加 td 的文本内容
-                    let text = cell.text().trim().to_string();
-                    if !text.is_empty() {
-                        elements.push(text);
-                    } else {
-                        elements.push("".to_string()); // 添加空字符串，占位
-                    }
+    let mut records = Vec::new();
+    // 找到表格的所有行
+    for row in document.find(
+        Attr("id", "donotscrapebgptools-prefixlist-tbody").descendant(Name("tr"))
+    ) {
+        let cells: Vec<_> = row
+            .find(Name("td"))
+            .map(|cell: select::node::Node<'_>| {
+                // 创建一个向量，用于存储结果
+                let mut elements = Vec::new();
 
-                    elements // 返回当前单元格解析出的内容
-                })
-                .collect();
-            if !cells.is_empty() {
-                let one_dimensional: Vec<String> = cells.into_iter().flatten().collect(); // 将二维向量转换为一维向量
-                // 调整元素排列顺序，以及过滤掉不要的元素
-                let transformed_vec = vec![
-                    one_dimensional
-                        .get(2)
-                        .cloned()
-                        .unwrap_or_else(|| "".to_string()), // 第3个元素
-                    one_dimensional
-                        .get(0)
-                        .cloned()
-                        .unwrap_or_else(|| "".to_string()), // 第1个元素
-                    one_dimensional
-                        .get(3)
-                        .cloned()
-                        .unwrap_or_else(|| "".to_string()) // 第4个元素
-                ];
-                // 判断CIDR的类型，4 或 6？
-                if let Ok(ip_network) = transformed_vec[0].parse::<IpNetwork>() {
-                    match ip_network {
-                        IpNetwork::V4(v4_network) => {
-                            if args.cidr_version == 4 {
-                                println!("抓取到内容：{:?}", transformed_vec);
-                                writer
-                                    .write_record(&transformed_vec)
-                                    .expect("Failed to write to csv file");
-                                writeln!(file, "{}", v4_network.to_string()).expect(
-                                    "Failed to write to txt file"
-                                );
-                            }
-                        }
-                        IpNetwork::V6(v6_network) => {
-                            if args.cidr_version == 6 {
-                                println!("抓取到内容：{:?}", transformed_vec);
-                                writer
-                                    .write_record(&transformed_vec)
-                                    .expect("Failed to write to csv file");
-                                writeln!(file, "{}", v6_network.to_string()).expect(
-                                    "Failed to write to txt file"
-                                );
-                            }
-                        }
+                // 查找 img 元素的国家代码
+                if let Some(img) = cell.find(Name("img")).next() {
+                    // 获取第一个 img
+                    if let Some(title) = img.attr("title") {
+                        // 获取第一个 img 的 title
+                        elements.push(title.to_string());
                     }
                 }
+
+                // 添加 td 的文本内容
+                let text = cell.text().trim().to_string();
+                if !text.is_empty() {
+                    elements.push(text);
+                } else {
+                    elements.push("".to_string()); // 添加空字符串，占位
+                }
+
+                elements // 返回当前单元格解析出的内容
+            })
+            .collect();
+        if !cells.is_empty() {
+            let one_dimensional: Vec<String> = cells.into_iter().flatten().collect(); // 将二维向量转换为一维向量
+            // 调整元素排列顺序，以及过滤掉不要的元素
+            let transformed_vec = vec![
+                one_dimensional
+                    .get(2)
+                    .cloned()
+                    .unwrap_or_else(|| "".to_string()), // 第3个元素
+                one_dimensional
+                    .first()
+                    .cloned()
+                    .unwrap_or_else(|| "".to_string()), // 第1个元素
+                one_dimensional
+                    .get(3)
+                    .cloned()
+                    .unwrap_or_else(|| "".to_string()) // 第4个元素
+            ];
+            // 判断CIDR的类型，4 或 6？
+            if let Ok(prefix) = transformed_vec[0].parse::<IpNetwork>() {
+                if
+                    (prefix.is_ipv4() && version == 4) ||
+                    (prefix.is_ipv6() && version == 6)
+                {
+                    debug!("{}：{:?}", tr!("抓取到内容", "Fetched row"), transformed_vec);
+                    records.push(PrefixRecord { prefix, row: transformed_vec });
+                }
             }
         }
-    } else {
-        println!("HTTP网页请求失败，状态码是: {}", response.status());
     }
-    Ok(())
+    Parsed {
+        header: &["prefix", "country_code", "description"],
+        records,
+        name: get_as_name_from_title(&document),
+    }
 }
@@ -0,0 +1,178 @@
+use crate::{ cidr::address_count, output::write_atomic };
+use std::{ collections::BTreeMap, error::Error, fs, io::Write, path::Path, sync::Mutex, time::Duration };
+use ipnetwork::IpNetwork;
+use regex::Regex;
+use reqwest::StatusCode;
+
+// 按 (来源, ASN, CIDR版本) 记录的指标
+type AsKey = (String, u32, u8);
+
+#[derive(Default)]
+struct Values {
+    fetch_attempts: BTreeMap<String, u64>,
+    fetch_failures: BTreeMap<String, u64>,
+    http_responses: BTreeMap<(String, u16), u64>,
+    parse_seconds: BTreeMap<String, (f64, u64)>, // (总耗时, 次数)
+    prefixes: BTreeMap<AsKey, usize>,
+    addresses: BTreeMap<AsKey, f64>,
+    last_success: BTreeMap<AsKey, u64>,
+}
+
+/// 下载过程中的Prometheus指标，写入node_exporter的textfile collector目录中的文件
+#[derive(Default)]
+pub struct Metrics {
+    values: Mutex<Values>,
+}
+
+impl Metrics {
+    // 一次HTTP请求(包括重试)的结果，网络错误时status为None
+    pub fn record_attempt(&self, source: &str, status: Option<StatusCode>) {
+        let mut values = self.values.lock().unwrap();
+        *values.fetch_attempts.entry(source.to_string()).or_default() += 1;
+        let ok = status.is_some_and(|s| s.is_success() || s == StatusCode::NOT_MODIFIED);
+        if !ok {
+            *values.fetch_failures.entry(source.to_string()).or_default() += 1;
+        }
+        if let Some(status) = status {
+            *values.http_responses.entry((source.to_string(), status.as_u16())).or_default() += 1;
+        }
+    }
+
+    pub fn record_parse(&self, source: &str, elapsed: Duration) {
+        let mut values = self.values.lock().unwrap();
+        let entry = values.parse_seconds.entry(source.to_string()).or_default();
+        entry.0 += elapsed.as_secs_f64();
+        entry.1 += 1;
+    }
+
+    // 处理后的前缀数和覆盖的地址数
+    pub fn record_prefixes(&self, source: &str, asn: u32, version: u8, prefixes: &[IpNetwork]) {
+        let key = (source.to_string(), asn, version);
+        let mut values = self.values.lock().unwrap();
+        values.prefixes.insert(key.clone(), prefixes.len());
+        // Prometheus的样本值是浮点数
+        values.addresses.insert(key, address_count(prefixes) as f64);
+    }
+
+    // 从上次写入的textfile中恢复各ASN最后一次成功的时间，本次运行失败的ASN保留原来的值，
+    // 这样才能按时间戳发现长时间没有更新的ASN
+    pub fn restore(&self, path: &Path) {
+        let Ok(content) = fs::read_to_string(path) else {
+            return;
+        };
+        let re = Regex::new(
+            r#"^download_as_cidrs_last_success_timestamp_seconds\{source="([^"]*)",asn="(\d+)",family="ipv([46])"\} (\d+)$"#
+        ).unwrap();
+        let mut values = self.values.lock().unwrap();
+        for caps in content.lines().filter_map(|line| re.captures(line)) {
+            if let (Ok(asn), Ok(version), Ok(timestamp)) = (caps[2].parse(), caps[3].parse(), caps[4].parse()) {
+                values.last_success.insert((caps[1].to_string(), asn, version), timestamp);
+            }
+        }
+    }
+
+    pub fn record_success(&self, source: &str, asn: u32, version: u8, timestamp: u64) {
+        let mut values = self.values.lock().unwrap();
+        values.last_success.insert((source.to_string(), asn, version), timestamp);
+    }
+
+    // 按Prometheus文本格式输出
+    pub fn render(&self) -> String {
+        let values = self.values.lock().unwrap();
+        let mut out = String::new();
+        // samples的第1项是指标名后面的部分：标签，summary还要在标签前加上 _sum 或 _count
+        let mut metric = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+            out.push_str(&format!("# HELP download_as_cidrs_{} {}\n", name, help));
+            out.push_str(&format!("# TYPE download_as_cidrs_{} {}\n", name, kind));
+            for (labels, value) in samples {
+                out.push_str(&format!("download_as_cidrs_{}{} {}\n", name, labels, value));
+            }
+        };
+        let source_label = |source: &String| format!("{{source=\"{}\"}}", source);
+        let as_label = |(source, asn, version): &AsKey| {
+            format!("{{source=\"{}\",asn=\"{}\",family=\"ipv{}\"}}", source, asn, version)
+        };
+
+        metric(
+            "fetch_attempts_total",
+            "counter",
+            "HTTP requests sent to the source, including retries",
+            values.fetch_attempts
+                .iter()
+                .map(|(k, v)| (source_label(k), v.to_string()))
+                .collect()
+        );
+        metric(
+            "fetch_failures_total",
+            "counter",
+            "HTTP requests that failed with a network error or an unsuccessful status",
+            values.fetch_failures
+                .iter()
+                .map(|(k, v)| (source_label(k), v.to_string()))
+                .collect()
+        );
+        metric(
+            "http_responses_total",
+            "counter",
+            "HTTP responses by status code",
+            values.http_responses
+                .iter()
+                .map(|((source, code), v)| {
+                    (format!("{{source=\"{}\",code=\"{}\"}}", source, code), v.to_string())
+                })
+                .collect()
+        );
+        metric(
+            "parse_duration_seconds",
+            "summary",
+            "Time spent parsing fetched pages",
+            values.parse_seconds
+                .iter()
+                .flat_map(|(k, (sum, count))| {
+                    [
+                        (format!("_sum{}", source_label(k)), sum.to_string()),
+                        (format!("_count{}", source_label(k)), count.to_string()),
+                    ]
+                })
+                .collect()
+        );
+        metric(
+            "prefixes",
+            "gauge",
+            "Prefixes written for the ASN after filtering",
+            values.prefixes
+                .iter()
+                .map(|(k, v)| (as_label(k), v.to_string()))
+                .collect()
+        );
+        metric(
+            "addresses",
+            "gauge",
+            "Addresses covered by the written prefixes",
+            values.addresses
+                .iter()
+                .map(|(k, v)| (as_label(k), v.to_string()))
+                .collect()
+        );
+        metric(
+            "last_success_timestamp_seconds",
+            "gauge",
+            "Unix time of the last successful download",
+            values.last_success
+                .iter()
+                .map(|(k, v)| (as_label(k), v.to_string()))
+                .collect()
+        );
+        out
+    }
+
+    // 原子地写入textfile，避免node_exporter读到写了一半的文件
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let content = self.render();
+        write_atomic(path, |file| {
+            file.write_all(content.as_bytes())?;
+            Ok(())
+        })?;
+        Ok(())
+    }
+}
@@ -1,12 +1,14 @@
+use ipnetwork::IpNetwork;
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
 pub struct ApiResponse {
     pub status: String,
+    #[serde(default)] // 出错时的响应没有data字段
     pub data: Data,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct Data {
     pub ipv4_prefixes: Vec<Prefix>,
     pub ipv6_prefixes: Vec<Prefix>,
@@ -25,3 +27,39 @@ pub struct Prefix {
 pub struct Parent {
     pub rir_name: Option<String>,
 }
+
+// 各个来源解析出的一条前缀记录，prefix用于过滤和写入txt，row是写入csv的各列内容
+#[derive(Debug, Clone)]
+pub struct PrefixRecord {
+    pub prefix: IpNetwork,
+    pub row: Vec<String>,
+}
+
+// 解析一个来源的页面得到的结果
+#[derive(Debug)]
+pub struct Parsed {
+    pub header: &'static [&'static str], // csv文件的表头(固定的英文列名，写入时再翻译)，与PrefixRecord::row的各列对应
+    pub records: Vec<PrefixRecord>,
+    pub name: String, // AS名称，取不到时为空
+}
+
+// bgp.he.net 前缀表格中的一行，按表头的列名取值；没有国旗时国家代码和国家名称为空
+#[derive(Debug, Clone)]
+pub struct HeNetPrefix {
+    pub prefix: IpNetwork,
+    pub country_code: String,
+    pub country_name: String,
+    pub description: String,
+}
+
+impl HeNetPrefix {
+    pub const HEADER: &'static [&'static str] = &["prefix", "country_code", "country_name", "description"];
+
+    // 转换为与HEADER各列对应的前缀记录
+    pub fn into_record(self) -> PrefixRecord {
+        PrefixRecord {
+            prefix: self.prefix,
+            row: vec![self.prefix.to_string(), self.country_code, self.country_name, self.description],
+        }
+    }
+}
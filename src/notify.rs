@@ -0,0 +1,125 @@
+use crate::i18n::tr;
+use std::{ error::Error, fmt::Write, str::FromStr };
+use ipnetwork::IpNetwork;
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::json;
+use tracing::warn;
+
+/// 一个ASN的一个CIDR版本，与上次输出相比的变化
+#[derive(Debug, Clone, Serialize)]
+pub struct PrefixChange {
+    pub asn: u32,
+    pub family: u8,
+    pub source: String,
+    pub name: String,
+    pub added: Vec<IpNetwork>,
+    pub removed: Vec<IpNetwork>,
+}
+
+impl PrefixChange {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+// 通知的消息格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SinkFormat {
+    Json, // {"changes": [...]}，包含增加和减少的前缀
+    Slack, // Slack兼容的 {"text": "..."}
+    Ntfy, // ntfy 的纯文本消息，标题放在 Title 请求头中
+}
+
+/// 前缀变化的通知目标，命令行中写作 [json=|slack=|ntfy=]URL
+#[derive(Debug, Clone)]
+pub struct Sink {
+    format: SinkFormat,
+    url: String,
+}
+
+impl FromStr for Sink {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, url) = match s.split_once('=') {
+            Some(("json", url)) => (SinkFormat::Json, url),
+            Some(("slack", url)) => (SinkFormat::Slack, url),
+            Some(("ntfy", url)) => (SinkFormat::Ntfy, url),
+            _ => (SinkFormat::Json, s),
+        };
+        reqwest::Url::parse(url).map_err(|e| tr!("无效的通知URL：{}，{}", "Invalid notification URL: {}, {}", url, e))?;
+        Ok(Sink { format, url: url.to_string() })
+    }
+}
+
+// 变化的文字摘要，用于Slack和ntfy
+fn summary(changes: &[&PrefixChange]) -> String {
+    let mut text = String::new();
+    for change in changes {
+        let line = tr!(
+            "AS{} {} IPv{} ({})：增加 {} 个，减少 {} 个前缀",
+            "AS{} {} IPv{} ({}): {} prefixes added, {} removed",
+            change.asn,
+            change.name,
+            change.family,
+            change.source,
+            change.added.len(),
+            change.removed.len()
+        );
+        writeln!(text, "{}", line).unwrap();
+        for prefix in &change.added {
+            writeln!(text, "+ {}", prefix).unwrap();
+        }
+        for prefix in &change.removed {
+            writeln!(text, "- {}", prefix).unwrap();
+        }
+    }
+    text
+}
+
+/// 把一次运行中前缀的增减发送到所有通知目标
+pub struct Notifier {
+    client: Client,
+    sinks: Vec<Sink>,
+}
+
+impl Notifier {
+    pub fn new(client: Client, sinks: Vec<Sink>) -> Self {
+        Notifier { client, sinks }
+    }
+
+    // 发送失败只报告，不影响下载结果
+    pub async fn notify(&self, changes: &[PrefixChange]) {
+        let changes: Vec<&PrefixChange> = changes
+            .iter()
+            .filter(|c| !c.is_empty())
+            .collect();
+        if changes.is_empty() {
+            return;
+        }
+        for sink in &self.sinks {
+            if let Err(e) = self.send(sink, &changes).await {
+                warn!("{}", tr!("发送前缀变化通知到 {} 失败：{}", "Failed to notify {} of prefix changes: {}", sink.url, e));
+            }
+        }
+    }
+
+    async fn send(&self, sink: &Sink, changes: &[&PrefixChange]) -> Result<(), Box<dyn Error>> {
+        let request = match sink.format {
+            SinkFormat::Json => self.client.post(&sink.url).json(&json!({ "changes": changes })),
+            SinkFormat::Slack => self.client.post(&sink.url).json(&json!({ "text": summary(changes) })),
+            SinkFormat::Ntfy =>
+                self.client
+                    .post(&sink.url)
+                    .header("Title", "AS prefix changes")
+                    .header("Tags", "globe_with_meridians")
+                    .body(summary(changes)),
+        };
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(tr!("状态码是: {}", "status: {}", response.status()).into());
+        }
+        Ok(())
+    }
+}
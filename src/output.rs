@@ -0,0 +1,178 @@
+use crate::i18n::tr;
+use std::{
+    error::Error,
+    ffi::OsString,
+    fs::{ self, File },
+    io::{ BufRead, BufReader, Write },
+    path::{ Path, PathBuf },
+    process,
+    time::{ SystemTime, UNIX_EPOCH },
+};
+use regex::Regex;
+
+// 默认的文件名模板，与原来的输出位置相同：以主机名命名的文件夹/AS{asn}_v{family}
+pub static DEFAULT_NAME_TEMPLATE: &str = "{source}/AS{asn}_v{family}";
+static PLACEHOLDERS: &[&str] = &["asn", "family", "source", "date", "name", "country"];
+
+/// 输出位置：输出目录加上文件名模板，或者标准输出
+#[derive(Debug, Clone)]
+pub enum OutputTarget {
+    Files {
+        dir: PathBuf,
+        template: String,
+    },
+    Stdout,
+}
+
+// 填充文件名模板用到的值
+pub struct NameVars<'a> {
+    pub asn: u32,
+    pub family: u8,
+    pub source: &'a str,
+    pub name: &'a str,
+    pub country: Option<&'a str>, // 按国家拆分输出时的国家代码
+}
+
+impl OutputTarget {
+    // output_dir 为"-"时输出到标准输出
+    pub fn new(output_dir: &str, template: &str) -> Result<Self, Box<dyn Error>> {
+        if output_dir == "-" {
+            return Ok(OutputTarget::Stdout);
+        }
+        // 检查模板中的占位符
+        let re = Regex::new(r"\{([^}]*)\}").unwrap();
+        for caps in re.captures_iter(template) {
+            if !PLACEHOLDERS.contains(&&caps[1]) {
+                return Err(
+                    tr!(
+                        "文件名模板中有未知的占位符：{{{}}}，可用的有：{{{}}}",
+                        "Unknown placeholder in the file name template: {{{}}}, available: {{{}}}",
+                        &caps[1],
+                        PLACEHOLDERS.join("}, {")
+                    ).into()
+                );
+            }
+        }
+        Ok(OutputTarget::Files { dir: PathBuf::from(output_dir), template: template.to_string() })
+    }
+
+    // 按模板得到不带扩展名的输出路径，并创建所在的文件夹；输出到标准输出时返回None
+    pub fn base_path(&self, vars: &NameVars) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        match self {
+            OutputTarget::Stdout => Ok(None),
+            OutputTarget::Files { dir, template } => {
+                let path = dir.join(render(template, vars));
+                if let Some(parent) = path.parent() {
+                    create_folder_if_not_exists(parent)?;
+                }
+                Ok(Some(path))
+            }
+        }
+    }
+}
+
+// 在不带扩展名的路径后面加上后缀，例如 ".csv"、"_invert.txt"
+pub fn with_suffix(base: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(base);
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+// 先写入同一目录下的临时文件，全部写完后再重命名为目标文件，读取方不会看到写了一半的文件；
+// 内容与原有文件相同时不重写，返回值表示文件是否有变化
+pub fn write_atomic<F>(path: &Path, write: F) -> Result<bool, Box<dyn Error>>
+    where F: FnOnce(&mut Vec<u8>) -> Result<(), Box<dyn Error>>
+{
+    let mut content = Vec::new();
+    write(&mut content)?;
+    if fs::read(path).is_ok_and(|previous| previous == content) {
+        return Ok(false);
+    }
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| tr!("输出路径不是文件：{}", "Output path is not a file: {}", path.display()))?
+        .to_string_lossy();
+    let tmp = path.with_file_name(format!(".{}.{}.tmp", file_name, process::id()));
+    let result = (|| {
+        let mut file = File::create(&tmp)?;
+        file.write_all(&content)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(true)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+// 上一次输出的文件中的前缀(csv文件取第1列，不计表头)；文件不存在时返回None
+pub fn read_previous(path: &Path, has_header: bool) -> Option<Vec<String>> {
+    let file = File::open(path).ok()?;
+    let prefixes = BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .skip(if has_header { 1 } else { 0 })
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.split(',').next().unwrap_or_default().trim().to_string())
+        .collect();
+    Some(prefixes)
+}
+
+// 文件夹不存在就创建
+fn create_folder_if_not_exists(folder_path: &Path) -> Result<(), std::io::Error> {
+    if !folder_path.as_os_str().is_empty() && !folder_path.exists() {
+        fs::create_dir_all(folder_path)?;
+    }
+    Ok(())
+}
+
+// 按国家拆分输出而模板中没有{country}时，在文件名后加上"_国家代码"
+fn render(template: &str, vars: &NameVars) -> String {
+    let country = vars.country.map(sanitize);
+    let mut path = template
+        .replace("{asn}", &vars.asn.to_string())
+        .replace("{family}", &vars.family.to_string())
+        .replace("{source}", vars.source)
+        .replace("{date}", &today())
+        .replace("{name}", &sanitize(vars.name))
+        .replace("{country}", country.as_deref().unwrap_or_default());
+    if let Some(country) = country {
+        if !template.contains("{country}") {
+            path.push('_');
+            path.push_str(&country);
+        }
+    }
+    path
+}
+
+// AS名称中不适合放进文件名的字符替换为"_"
+fn sanitize(name: &str) -> String {
+    let mut result = String::new();
+    for c in name.trim().chars() {
+        if c.is_alphanumeric() || c == '-' || c == '.' {
+            result.push(c);
+        } else if !result.ends_with('_') {
+            result.push('_');
+        }
+    }
+    result.trim_matches('_').to_string()
+}
+
+// 当前的UTC日期，格式为 YYYY-MM-DD
+fn today() -> String {
+    let days = (SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() /
+        86400) as i64;
+    // 由1970-01-01起的天数换算成公历日期
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (if month <= 2 { 1 } else { 0 });
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
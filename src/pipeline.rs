@@ -0,0 +1,623 @@
+use crate::{
+    bogon::Bogons,
+    i18n::{ column_label, tr, HeaderMode },
+    cidr::{ aggregate, normalize, read_cidr_file, subtract },
+    metrics::Metrics,
+    models::{ Parsed, PrefixRecord },
+    notify::PrefixChange,
+    output::{ read_previous, with_suffix, write_atomic, NameVars, OutputTarget },
+    rpki::{ RpkiState, Vrps },
+    Args,
+};
+use std::{
+    collections::{ BTreeMap, BTreeSet },
+    error::Error,
+    fmt,
+    io::Write,
+    ops::RangeInclusive,
+    path::Path,
+    sync::Arc,
+};
+use ipnetwork::IpNetwork;
+use clap::ValueEnum;
+use tracing::{ info, warn };
+use csv::Writer;
+
+/// 输出文件的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// 带有国家代码、描述等信息的csv文件
+    Csv,
+    /// 每行一个CIDR的txt文件
+    Txt,
+}
+
+/// 拆分输出文件的依据
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SplitBy {
+    /// 每个国家代码一组文件
+    Country,
+}
+
+/// 按RPKI验证状态过滤前缀
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RpkiFilter {
+    /// 保留全部前缀，只标注状态
+    All,
+    /// 只保留Valid的前缀
+    ValidOnly,
+    /// 去掉Invalid的前缀
+    NotInvalid,
+}
+
+impl RpkiFilter {
+    fn accept(&self, state: RpkiState) -> bool {
+        match self {
+            RpkiFilter::All => true,
+            RpkiFilter::ValidOnly => state == RpkiState::Valid,
+            RpkiFilter::NotInvalid => state != RpkiState::Invalid,
+        }
+    }
+}
+
+/// 解析结果明显异常(没有前缀，或者比上次骤减)，通常是网站改版导致解析器失效
+#[derive(Debug)]
+pub struct ParserBroken {
+    pub asn: u32,
+    pub version: u8,
+    pub reason: String,
+}
+
+impl fmt::Display for ParserBroken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = tr!(
+            "AS{} 的IPv{}{}，解析器可能已失效(parser likely broken)，未替换原有文件",
+            "AS{} IPv{}: {}, parser likely broken, existing files were kept",
+            self.asn,
+            self.version,
+            self.reason
+        );
+        f.write_str(&message)
+    }
+}
+
+impl Error for ParserBroken {}
+
+// 与上次输出相比增加和减少的前缀
+type Diff = (Vec<IpNetwork>, Vec<IpNetwork>);
+
+// 国家代码所在的列(固定的英文列名为country_code)
+pub fn country_index(header: &[String]) -> Option<usize> {
+    header.iter().position(|h| h == "country_code")
+}
+
+fn record_country(record: &PrefixRecord, index: Option<usize>) -> Option<&str> {
+    index.and_then(|i| record.row.get(i)).map(|c| c.trim())
+}
+
+// 从每个前缀中减去networks，部分重叠的前缀拆分成剩余的CIDR，每个CIDR复制一份csv行；
+// 有变化的前缀(包括整个被去掉的)调用report报告
+fn subtract_records(
+    records: Vec<PrefixRecord>,
+    networks: &[IpNetwork],
+    mut report: impl FnMut(&PrefixRecord, &[IpNetwork])
+) -> Vec<PrefixRecord> {
+    let mut result = Vec::with_capacity(records.len());
+    for r in records {
+        let remaining = subtract(r.prefix, networks);
+        if remaining.len() != 1 || remaining[0] != r.prefix {
+            report(&r, &remaining);
+        }
+        for prefix in remaining {
+            let mut row = r.row.clone();
+            if let Some(first) = row.first_mut() {
+                *first = prefix.to_string();
+            }
+            result.push(PrefixRecord { prefix, row });
+        }
+    }
+    result
+}
+
+fn join(networks: &[IpNetwork]) -> String {
+    networks
+        .iter()
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// 解析出的前缀在写入csv和txt文件之前，要经过的处理步骤
+pub struct Pipeline {
+    len_v4: RangeInclusive<u8>,
+    len_v6: RangeInclusive<u8>,
+    bogons: Option<Bogons>,
+    excludes: Vec<IpNetwork>,
+    vrps: Option<Vrps>,
+    rpki_filter: RpkiFilter,
+    // 只保留这些国家代码(大写)的前缀；为空时不过滤
+    countries: Vec<String>,
+    split_by: Option<SplitBy>,
+    // 反选时要从全部地址中除去的额外地址段；None表示不生成反选列表
+    invert: Option<Vec<IpNetwork>>,
+    aggregate: bool,
+    formats: Vec<OutputFormat>,
+    csv_header: HeaderMode,
+    target: OutputTarget,
+    // 前缀数比上次减少超过该百分比时保留原有文件
+    keep_on_shrink: Option<u8>,
+    // 解析器失效检测：最少的前缀数，以及比上次减少的最大百分比
+    min_prefixes: usize,
+    max_drop: Option<u8>,
+    metrics: Arc<Metrics>,
+}
+
+impl Pipeline {
+    pub fn from_args(args: &Args, metrics: Arc<Metrics>) -> Result<Self, Box<dyn Error>> {
+        let bogons = if args.drop_bogons {
+            Some(Bogons::new(args.bogon_file.as_deref())?)
+        } else {
+            None
+        };
+        // --exclude 的每一项可以是CIDR，也可以是文件路径
+        let mut excludes = Vec::new();
+        for item in &args.exclude {
+            match item.parse::<IpNetwork>() {
+                Ok(network) => excludes.push(normalize(network)),
+                Err(e) if !Path::new(item).exists() => {
+                    return Err(
+                        tr!(
+                            "--exclude 的 {} 既不是有效的CIDR({})，也不是可读取的文件",
+                            "--exclude item {} is neither a valid CIDR ({}) nor a readable file",
+                            item,
+                            e
+                        ).into()
+                    );
+                }
+                Err(_) => excludes.extend(read_cidr_file(Path::new(item))?.into_iter().map(normalize)),
+            }
+        }
+        let vrps = match &args.vrp {
+            Some(path) => {
+                let vrps = Vrps::load(path)?;
+                info!("{}", tr!("已加载 {} 条VRP：{}", "Loaded {} VRPs: {}", vrps.len(), path.display()));
+                Some(vrps)
+            }
+            None => None,
+        };
+
+        let invert = if args.invert {
+            if args.invert_exclude_private {
+                Some(Bogons::new(None)?.networks().to_vec())
+            } else {
+                Some(Vec::new())
+            }
+        } else {
+            None
+        };
+
+        Ok(Pipeline {
+            len_v4: args.min_len_v4.unwrap_or(0)..=args.max_len_v4.unwrap_or(32),
+            len_v6: args.min_len_v6.unwrap_or(0)..=args.max_len_v6.unwrap_or(128),
+            bogons,
+            excludes,
+            vrps,
+            rpki_filter: args.rpki,
+            countries: args.country
+                .iter()
+                .map(|c| c.trim().to_uppercase())
+                .collect(),
+            split_by: args.split_by,
+            invert,
+            aggregate: args.aggregate,
+            formats: args.format.clone(),
+            csv_header: args.csv_header,
+            target: OutputTarget::new(&args.output_dir, &args.name_template)?,
+            keep_on_shrink: args.keep_on_shrink,
+            min_prefixes: args.min_prefixes,
+            max_drop: args.max_drop,
+            metrics,
+        })
+    }
+
+    // 处理前缀记录：规范化、国家过滤、RPKI验证、保留地址过滤、排除、长度过滤，返回csv表头(固定的英文列名)和处理后的记录
+    pub fn process(&self, parsed: Parsed, asn: u32) -> (Vec<String>, Vec<PrefixRecord>) {
+        let mut header: Vec<String> = parsed.header
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let mut records = parsed.records;
+
+        // 规范化带有主机位的前缀，csv的第1列同样是前缀，一起修正
+        for r in records.iter_mut() {
+            let normalized = normalize(r.prefix);
+            if normalized != r.prefix {
+                warn!(
+                    "{}",
+                    tr!("前缀带有主机位，已规范化：{} -> {}", "Prefix has host bits set, normalized: {} -> {}", r.prefix, normalized)
+                );
+                r.prefix = normalized;
+                if let Some(first) = r.row.first_mut() {
+                    *first = normalized.to_string();
+                }
+            }
+        }
+
+        // 只保留指定国家代码的前缀，没有国家代码的前缀也去掉
+        if !self.countries.is_empty() {
+            let country = country_index(&header);
+            let before = records.len();
+            records.retain(|r| {
+                let keep = record_country(r, country).is_some_and(|c| self.countries.contains(&c.to_uppercase()));
+                if !keep {
+                    info!("{}", tr!("国家代码不在 --country 中，已去掉：{:?}", "Country code not in --country, dropped: {:?}", r.row));
+                }
+                keep
+            });
+            if records.len() < before {
+                info!(
+                    "{}",
+                    tr!(
+                        "按国家代码 {} 过滤，去掉 {} 个前缀",
+                        "Filtered by country code {}, dropped {} prefixes",
+                        self.countries.join(","),
+                        before - records.len()
+                    )
+                );
+            }
+        }
+
+        // RPKI起源验证：添加一列状态，并按照过滤条件去掉不要的前缀；
+        // 在排除之前验证，拆分出的剩余部分沿用原来宣告的前缀的状态
+        if let Some(vrps) = &self.vrps {
+            header.push("rpki_state".to_string());
+            records.retain_mut(|r| {
+                let state = vrps.validate(r.prefix, asn);
+                r.row.push(state.to_string());
+                let keep = self.rpki_filter.accept(state);
+                if !keep {
+                    info!("{}", tr!("RPKI状态为{}，已过滤：{}", "RPKI state {}, filtered: {}", state, r.prefix));
+                }
+                keep
+            });
+        }
+
+        // 去掉保留地址段：位于保留地址段内的前缀整个去掉，包含保留地址段的前缀(例如 100.0.0.0/8)只减去重叠部分
+        if let Some(bogons) = &self.bogons {
+            let mut changed = 0;
+            records = subtract_records(records, bogons.networks(), |r, remaining| {
+                changed += 1;
+                let bogon = bogons.matching(r.prefix).unwrap_or(r.prefix);
+                if remaining.is_empty() {
+                    info!("{}", tr!("位于保留地址段 {} 内，已去掉：{:?}", "Inside bogon {}, dropped: {:?}", bogon, r.row));
+                } else {
+                    info!(
+                        "{}",
+                        tr!(
+                            "{} 包含保留地址段 {}，剩余：{}",
+                            "{} contains bogon {}, remaining: {}",
+                            r.prefix,
+                            bogon,
+                            join(remaining)
+                        )
+                    );
+                }
+            });
+            if changed > 0 {
+                info!("{}", tr!("共有 {} 个前缀与保留地址段重叠", "{} prefixes overlapped bogons", changed));
+            }
+        }
+
+        // 减去排除的地址段
+        if !self.excludes.is_empty() {
+            records = subtract_records(records, &self.excludes, |r, remaining| {
+                info!(
+                    "{}",
+                    tr!("{} 与排除列表重叠，剩余：{}", "{} overlaps the exclude list, remaining: {}", r.prefix, join(remaining))
+                );
+            });
+        }
+
+        // 按前缀长度过滤，排除后拆分出的剩余部分同样受长度限制
+        records.retain(|r| {
+            let range = match r.prefix {
+                IpNetwork::V4(_) => &self.len_v4,
+                IpNetwork::V6(_) => &self.len_v6,
+            };
+            let keep = range.contains(&r.prefix.prefix());
+            if !keep {
+                info!(
+                    "{}",
+                    tr!(
+                        "前缀长度 /{} 不在 /{}~/{} 范围内，已去掉：{}",
+                        "Prefix length /{} outside /{}-/{}, dropped: {}",
+                        r.prefix.prefix(),
+                        range.start(),
+                        range.end(),
+                        r.prefix
+                    )
+                );
+            }
+            keep
+        });
+
+        (header, records)
+    }
+
+    // 解析出的前缀太少，说明页面结构可能变了
+    pub fn check_parsed(&self, parsed: &Parsed, asn: u32, version: u8) -> Result<(), ParserBroken> {
+        if parsed.records.len() < self.min_prefixes {
+            return Err(ParserBroken {
+                asn,
+                version,
+                reason: tr!(
+                    "只解析到 {} 个前缀，少于 --min-prefixes {}",
+                    "only {} prefixes parsed, fewer than --min-prefixes {}",
+                    parsed.records.len(),
+                    self.min_prefixes
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    // txt的内容，需要时聚合成最少的CIDR
+    pub fn txt_prefixes(&self, records: &[PrefixRecord]) -> Vec<IpNetwork> {
+        let prefixes: Vec<IpNetwork> = records
+            .iter()
+            .map(|r| r.prefix)
+            .collect();
+        if self.aggregate {
+            let aggregated = aggregate(&prefixes);
+            info!("{}", tr!("{} 个前缀聚合为 {} 个CIDR", "Aggregated {} prefixes into {} CIDRs", prefixes.len(), aggregated.len()));
+            aggregated
+        } else {
+            prefixes
+        }
+    }
+
+    // 处理前缀记录，然后写入到输出目录下按模板命名的csv文件和txt文件，或者标准输出；
+    // 有文件内容发生变化时，返回与上次输出相比增加和减少的前缀
+    pub fn write(
+        &self,
+        parsed: Parsed,
+        source: &str,
+        asn: u32,
+        version: u8
+    ) -> Result<Option<PrefixChange>, Box<dyn Error>> {
+        self.check_parsed(&parsed, asn, version)?;
+        let name = parsed.name.clone();
+        let (header, records) = self.process(parsed, asn);
+        let country = country_index(&header);
+        let header: Vec<String> = header
+            .iter()
+            .map(|key| column_label(key, self.csv_header))
+            .collect();
+        let written: Vec<IpNetwork> = records
+            .iter()
+            .map(|r| r.prefix)
+            .collect();
+        self.metrics.record_prefixes(source, asn, version, &written);
+
+        if matches!(self.target, OutputTarget::Stdout) {
+            // 输出到标准输出：选了txt格式时只输出txt的内容，否则输出csv
+            let stdout = std::io::stdout();
+            let mut out = stdout.lock();
+            if self.formats.contains(&OutputFormat::Txt) {
+                for prefix in &self.txt_prefixes(&records) {
+                    writeln!(out, "{}", prefix)?;
+                }
+            } else {
+                let mut writer = Writer::from_writer(out);
+                writer.write_record(&header)?;
+                for record in &records {
+                    writer.write_record(&record.row)?;
+                }
+                writer.flush()?;
+            }
+            return Ok(None);
+        }
+
+        // 没有解析到任何前缀时不替换原有文件
+        if records.is_empty() {
+            warn!("{}", tr!("AS{} 的IPv{}没有解析到任何前缀，保留原有文件", "AS{} IPv{}: no prefixes left, existing files were kept", asn, version));
+            return Ok(None);
+        }
+
+        // 按国家拆分时每个国家代码写一组文件，没有国家代码的前缀归入"unknown"
+        let groups: Vec<(Option<String>, Vec<PrefixRecord>)> = match self.split_by {
+            Some(SplitBy::Country) => {
+                let mut groups: BTreeMap<String, Vec<PrefixRecord>> = BTreeMap::new();
+                for record in records {
+                    let code = record_country(&record, country)
+                        .filter(|c| !c.is_empty())
+                        .map(|c| c.to_uppercase())
+                        .unwrap_or_else(|| "unknown".to_string());
+                    groups.entry(code).or_default().push(record);
+                }
+                groups
+                    .into_iter()
+                    .map(|(code, records)| (Some(code), records))
+                    .collect()
+            }
+            None => vec![(None, records)],
+        };
+
+        let mut change: Option<Diff> = None;
+        for (country, records) in groups {
+            let vars = NameVars { asn, family: version, source, name: &name, country: country.as_deref() };
+            let Some(base) = self.target.base_path(&vars)? else {
+                continue;
+            };
+            if let Some((added, removed)) = self.write_files(&base, &header, &records, asn, version)? {
+                let (all_added, all_removed) = change.get_or_insert_with(Default::default);
+                all_added.extend(added);
+                all_removed.extend(removed);
+            }
+        }
+
+        Ok(
+            change.map(|(mut added, mut removed)| {
+                added.sort();
+                removed.sort();
+                PrefixChange {
+                    asn,
+                    family: version,
+                    source: source.to_string(),
+                    name,
+                    added,
+                    removed,
+                }
+            })
+        )
+    }
+
+    // 写入一组文件(不拆分时就是该ASN该版本的全部文件)；有文件内容变化时返回增加和减少的前缀
+    fn write_files(
+        &self,
+        base: &Path,
+        header: &[String],
+        records: &[PrefixRecord],
+        asn: u32,
+        version: u8
+    ) -> Result<Option<Diff>, Box<dyn Error>> {
+        let prefixes = self.txt_prefixes(records);
+
+        // 与上次输出的文件比较前缀数：骤减时运行失败，或者保留原有文件
+        let (previous, current): (Option<Vec<String>>, Vec<IpNetwork>) = if
+            self.formats.contains(&OutputFormat::Txt)
+        {
+            (read_previous(&with_suffix(base, ".txt"), false), prefixes.clone())
+        } else {
+            (
+                read_previous(&with_suffix(base, ".csv"), true),
+                records
+                    .iter()
+                    .map(|r| r.prefix)
+                    .collect(),
+            )
+        };
+        let previous_count = previous.as_ref().map(Vec::len);
+        let shrunk = |percent: u8| {
+            previous_count.is_some_and(
+                |previous| (current.len() as f64) < (previous as f64) * (1.0 - (percent as f64) / 100.0)
+            )
+        };
+        if let Some(percent) = self.max_drop {
+            if shrunk(percent) {
+                return Err(
+                    Box::new(ParserBroken {
+                        asn,
+                        version,
+                        reason: tr!(
+                            "前缀数从 {} 减少到 {}，超过 --max-drop {}%",
+                            "prefix count dropped from {} to {}, more than --max-drop {}%",
+                            previous_count.unwrap_or_default(),
+                            current.len(),
+                            percent
+                        ),
+                    })
+                );
+            }
+        }
+        if let Some(percent) = self.keep_on_shrink {
+            if shrunk(percent) {
+                warn!(
+                    "{}",
+                    tr!(
+                        "AS{} 的IPv{}前缀数从 {} 减少到 {}，超过 {}%，保留原有文件",
+                        "AS{} IPv{}: prefix count dropped from {} to {}, more than {}%, existing files were kept",
+                        asn,
+                        version,
+                        previous_count.unwrap_or_default(),
+                        current.len(),
+                        percent
+                    )
+                );
+                return Ok(None);
+            }
+        }
+
+        // 写入csv文件
+        let mut changed = false;
+        if self.formats.contains(&OutputFormat::Csv) {
+            changed |= write_atomic(&with_suffix(base, ".csv"), |file| {
+                let mut writer = Writer::from_writer(file);
+                writer.write_record(header)?;
+                for record in records {
+                    writer.write_record(&record.row)?;
+                }
+                writer.flush()?;
+                Ok(())
+            })?;
+        }
+
+        // 写入txt文件
+        if self.formats.contains(&OutputFormat::Txt) {
+            changed |= write_atomic(&with_suffix(base, ".txt"), |file| {
+                for prefix in &prefixes {
+                    writeln!(file, "{}", prefix)?;
+                }
+                Ok(())
+            })?;
+        }
+
+        // 反选：从 0.0.0.0/0 或 ::/0 中减去下载到的前缀，得到最少的CIDR列表
+        if let Some(extra) = &self.invert {
+            let universe: IpNetwork = match version {
+                4 => "0.0.0.0/0".parse()?,
+                _ => "::/0".parse()?,
+            };
+            let mut excludes = extra.clone();
+            excludes.extend(records.iter().map(|r| r.prefix));
+            let complement = subtract(universe, &excludes);
+
+            let output_invert = with_suffix(base, "_invert.txt");
+            changed |= write_atomic(&output_invert, |file| {
+                for network in &complement {
+                    writeln!(file, "{}", network)?;
+                }
+                Ok(())
+            })?;
+            info!(
+                "{}",
+                tr!("反选得到 {} 个CIDR，已保存到：{}", "Inverted list has {} CIDRs, saved to {}", complement.len(), output_invert.display())
+            );
+        }
+
+        if !changed {
+            return Ok(None);
+        }
+        // 与上次输出的前缀比较；没有上次的文件时不计算增减
+        let (mut added, mut removed) = (Vec::new(), Vec::new());
+        if let Some(previous) = previous {
+            let previous: BTreeSet<IpNetwork> = previous
+                .iter()
+                .filter_map(|p| p.parse().ok())
+                .collect();
+            let current: BTreeSet<IpNetwork> = current.into_iter().collect();
+            added = current.difference(&previous).copied().collect();
+            removed = previous.difference(&current).copied().collect();
+        }
+        Ok(Some((added, removed)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rpki_filter_accept() {
+        use RpkiState::*;
+        let cases = [
+            (RpkiFilter::All, [true, true, true]),
+            (RpkiFilter::ValidOnly, [true, false, false]),
+            (RpkiFilter::NotInvalid, [true, false, true]),
+        ];
+        for (filter, expected) in cases {
+            let accepted = [Valid, Invalid, NotFound].map(|state| filter.accept(state));
+            assert_eq!(accepted, expected, "{:?}", filter);
+        }
+    }
+}
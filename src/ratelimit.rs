@@ -0,0 +1,113 @@
+use crate::{ i18n::tr, API_URL };
+use std::{ cmp::Ordering, fmt::Display, str::FromStr, time::Duration };
+use tokio::{ sync::{ Mutex, Semaphore, SemaphorePermit }, time::Instant };
+
+/// 单个网站的请求限速器：令牌桶控制每秒请求数，信号量控制最大并发连接数。
+/// 多个ASN同时下载时，所有任务共用同一个限速器。
+pub struct HostLimiter {
+    rate: f64,
+    capacity: f64,
+    bucket: Mutex<(f64, Instant)>, // (剩余令牌数, 上次补充令牌的时间)
+    connections: Semaphore,
+}
+
+impl HostLimiter {
+    pub fn new(requests_per_second: f64, max_connections: usize) -> Self {
+        // 桶的容量至少为1，允许短时间内的少量突发请求
+        let capacity = requests_per_second.max(1.0);
+        HostLimiter {
+            rate: requests_per_second,
+            capacity,
+            bucket: Mutex::new((capacity, Instant::now())),
+            connections: Semaphore::new(max_connections.max(1)),
+        }
+    }
+
+    // 等待拿到一个连接名额和一个令牌，返回的permit在释放前一直占用连接名额
+    pub async fn acquire(&self) -> SemaphorePermit<'_> {
+        let permit = self.connections.acquire().await.expect("semaphore closed");
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().await;
+                let now = Instant::now();
+                let (tokens, last) = *bucket;
+                let tokens = (tokens + now.duration_since(last).as_secs_f64() * self.rate).min(
+                    self.capacity
+                );
+                if tokens >= 1.0 {
+                    *bucket = (tokens - 1.0, now);
+                    return permit;
+                }
+                *bucket = (tokens, now);
+                Duration::from_secs_f64((1.0 - tokens) / self.rate)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// 限速参数的一项，命令行中写作 VALUE 或 HOST=VALUE：
+/// 不带主机名时作用于所有所选来源，带主机名(API_URL中的一项)时只作用于该来源并优先使用
+#[derive(Debug, Clone)]
+pub struct PerSource<T> {
+    host: Option<String>,
+    value: T,
+}
+
+// 最小的每秒请求数：再小的话等待令牌的时间会超出Duration的范围
+const MIN_RATE: f64 = 0.001;
+
+/// 限速参数的取值，除了大于0之外的额外检查
+pub trait LimitValue: FromStr + PartialOrd + Default {
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl LimitValue for usize {}
+
+impl LimitValue for f64 {
+    fn validate(&self) -> Result<(), String> {
+        if !self.is_finite() || *self < MIN_RATE {
+            return Err(
+                tr!("每秒请求数必须是不小于{}的有限数", "requests per second must be a finite number of at least {}", MIN_RATE)
+            );
+        }
+        Ok(())
+    }
+}
+
+impl<T> FromStr for PerSource<T> where T: LimitValue, T::Err: Display {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (host, value) = match s.split_once('=') {
+            Some((host, value)) => {
+                if !API_URL.contains(&host) {
+                    return Err(
+                        tr!("未知的来源：{}，可用的有：{}", "Unknown source: {}, available: {}", host, API_URL.join(", "))
+                    );
+                }
+                (Some(host.to_string()), value)
+            }
+            None => (None, s),
+        };
+        let value: T = value.parse().map_err(|e: T::Err| e.to_string())?;
+        // 同时排除了NaN：NaN与0无法比较
+        if value.partial_cmp(&T::default()) != Some(Ordering::Greater) {
+            return Err(tr!("值必须大于0", "value must be greater than 0"));
+        }
+        value.validate()?;
+        Ok(PerSource { host, value })
+    }
+}
+
+// 某个来源的限速值：该来源专用的值优先，其次是不带主机名的值；都没有时返回None
+pub fn for_source<T: Copy>(limits: &[PerSource<T>], host: &str) -> Option<T> {
+    limits
+        .iter()
+        .rev()
+        .find(|l| l.host.as_deref() == Some(host))
+        .or_else(|| limits.iter().rev().find(|l| l.host.is_none()))
+        .map(|l| l.value)
+}
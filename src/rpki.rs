@@ -0,0 +1,244 @@
+use crate::i18n::tr;
+use std::{ collections::HashMap, error::Error, fmt, fs, path::Path };
+use ipnetwork::IpNetwork;
+use serde::Deserialize;
+
+// RFC 6811 定义的路由起源验证状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpkiState {
+    Valid,
+    Invalid,
+    NotFound,
+}
+
+impl fmt::Display for RpkiState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            RpkiState::Valid => "Valid",
+            RpkiState::Invalid => "Invalid",
+            RpkiState::NotFound => "NotFound",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// rpki-client 的asn是数字，Routinator 的asn是"AS13335"这样的字符串
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum JsonAsn {
+    Number(u32),
+    Text(String),
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonRoa {
+    asn: JsonAsn,
+    prefix: String,
+    #[serde(rename = "maxLength")]
+    max_length: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonVrps {
+    roas: Vec<JsonRoa>,
+}
+
+/// 从本地VRP导出文件(rpki-client / Routinator 的JSON或CSV格式)加载的ROA集合
+pub struct Vrps {
+    // 键为(是否IPv6, 前缀长度, 网络地址)，值为该前缀下所有的(asn, maxLength)
+    roas: HashMap<(bool, u8, u128), Vec<(u32, u8)>>,
+}
+
+impl Vrps {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path).map_err(|e| {
+            tr!("无法读取VRP文件 {}：{}", "Cannot read VRP file {}: {}", path.display(), e)
+        })?;
+        let mut vrps = Vrps { roas: HashMap::new() };
+
+        if content.trim_start().starts_with('{') {
+            let json: JsonVrps = serde_json::from_str(&content)?;
+            for roa in json.roas {
+                let asn = match roa.asn {
+                    JsonAsn::Number(n) => n,
+                    JsonAsn::Text(s) => parse_asn(&s)?,
+                };
+                vrps.insert(asn, roa.prefix.parse()?, roa.max_length);
+            }
+        } else {
+            // CSV的前3列：ASN,IP Prefix,Max Length（后面的Trust Anchor、Expires等列忽略）
+            let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(content.as_bytes());
+            for record in reader.records() {
+                let record = record?;
+                if record.len() < 3 {
+                    return Err(tr!("VRP文件中有无效的行：{:?}", "Invalid line in the VRP file: {:?}", record).into());
+                }
+                vrps.insert(parse_asn(&record[0])?, record[1].trim().parse()?, record[2].trim().parse()?);
+            }
+        }
+
+        Ok(vrps)
+    }
+
+    fn insert(&mut self, asn: u32, prefix: IpNetwork, max_length: u8) {
+        self.roas
+            .entry(network_key(prefix, prefix.prefix()))
+            .or_default()
+            .push((asn, max_length));
+    }
+
+    pub fn len(&self) -> usize {
+        self.roas.values().map(Vec::len).sum()
+    }
+
+    // 按照 RFC 6811 计算某个前缀在给定起源ASN下的验证状态
+    pub fn validate(&self, prefix: IpNetwork, origin_asn: u32) -> RpkiState {
+        let mut covered = false;
+        for len in 0..=prefix.prefix() {
+            if let Some(roas) = self.roas.get(&network_key(prefix, len)) {
+                covered = true;
+                // AS0 的ROA永远不会匹配任何路由
+                if
+                    roas
+                        .iter()
+                        .any(|&(asn, max_length)| {
+                            asn != 0 && asn == origin_asn && prefix.prefix() <= max_length
+                        })
+                {
+                    return RpkiState::Valid;
+                }
+            }
+        }
+        if covered {
+            RpkiState::Invalid
+        } else {
+            RpkiState::NotFound
+        }
+    }
+}
+
+// 将前缀截断到len位后，作为查找用的键
+fn network_key(prefix: IpNetwork, len: u8) -> (bool, u8, u128) {
+    match prefix {
+        IpNetwork::V4(net) => {
+            let addr = u32::from(net.network()) as u128;
+            let mask = if len == 0 { 0 } else { (u32::MAX << (32 - len)) as u128 };
+            (false, len, addr & mask)
+        }
+        IpNetwork::V6(net) => {
+            let addr = u128::from(net.network());
+            let mask = if len == 0 { 0 } else { u128::MAX << (128 - len) };
+            (true, len, addr & mask)
+        }
+    }
+}
+
+fn parse_asn(s: &str) -> Result<u32, Box<dyn Error>> {
+    let s = s.trim();
+    let digits = s.strip_prefix("AS").or_else(|| s.strip_prefix("as")).unwrap_or(s);
+    Ok(digits.parse()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn load(content: &str) -> Vrps {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        Vrps::load(file.path()).unwrap()
+    }
+
+    fn net(s: &str) -> IpNetwork {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn validate_follows_rfc6811() {
+        let vrps = load(
+            "ASN,IP Prefix,Max Length,Trust Anchor\n\
+             AS13335,1.1.1.0/24,24,apnic\n\
+             AS13335,104.16.0.0/12,20,arin\n\
+             AS0,192.0.2.0/24,32,arin\n\
+             AS13335,2606:4700::/32,48,arin\n"
+        );
+        assert_eq!(vrps.len(), 4);
+        // 与ROA完全相同
+        assert_eq!(vrps.validate(net("1.1.1.0/24"), 13335), RpkiState::Valid);
+        // 更具体的前缀在maxLength以内
+        assert_eq!(vrps.validate(net("104.16.0.0/13"), 13335), RpkiState::Valid);
+        assert_eq!(vrps.validate(net("104.16.0.0/20"), 13335), RpkiState::Valid);
+        // 超过maxLength
+        assert_eq!(vrps.validate(net("104.16.0.0/21"), 13335), RpkiState::Invalid);
+        assert_eq!(vrps.validate(net("1.1.1.0/25"), 13335), RpkiState::Invalid);
+        // 被覆盖但起源ASN不同
+        assert_eq!(vrps.validate(net("1.1.1.0/24"), 64500), RpkiState::Invalid);
+        // AS0 的ROA覆盖的前缀不会是Valid，即使起源也是0
+        assert_eq!(vrps.validate(net("192.0.2.0/24"), 0), RpkiState::Invalid);
+        assert_eq!(vrps.validate(net("192.0.2.128/25"), 64500), RpkiState::Invalid);
+        // 没有ROA覆盖，包括比ROA更短的前缀
+        assert_eq!(vrps.validate(net("8.8.8.0/24"), 15169), RpkiState::NotFound);
+        assert_eq!(vrps.validate(net("1.1.0.0/16"), 13335), RpkiState::NotFound);
+        // IPv6，且不会与IPv4的ROA混淆
+        assert_eq!(vrps.validate(net("2606:4700:10::/44"), 13335), RpkiState::Valid);
+        assert_eq!(vrps.validate(net("2606:4700::/64"), 13335), RpkiState::Invalid);
+        assert_eq!(vrps.validate(net("::/0"), 13335), RpkiState::NotFound);
+    }
+
+    #[test]
+    fn validate_with_several_roas_for_one_prefix() {
+        let vrps = load("ASN,IP Prefix,Max Length\n64500,10.0.0.0/8,8\n64501,10.0.0.0/8,24\n");
+        assert_eq!(vrps.validate(net("10.1.0.0/16"), 64500), RpkiState::Invalid);
+        assert_eq!(vrps.validate(net("10.1.0.0/16"), 64501), RpkiState::Valid);
+        assert_eq!(vrps.validate(net("10.0.0.0/8"), 64500), RpkiState::Valid);
+    }
+
+    #[test]
+    fn load_rpki_client_json() {
+        let vrps = load(
+            r#"{"metadata": {"buildtime": "2024-01-01T00:00:00Z"}, "roas": [
+                {"asn": 13335, "prefix": "1.1.1.0/24", "maxLength": 24, "ta": "apnic", "expires": 1704067200},
+                {"asn": 13335, "prefix": "2606:4700::/32", "maxLength": 48, "ta": "arin", "expires": 1704067200}
+            ]}"#
+        );
+        assert_eq!(vrps.len(), 2);
+        assert_eq!(vrps.validate(net("1.1.1.0/24"), 13335), RpkiState::Valid);
+        assert_eq!(vrps.validate(net("2606:4700::/48"), 13335), RpkiState::Valid);
+    }
+
+    #[test]
+    fn load_routinator_json() {
+        let vrps = load(
+            r#"{"roas": [{"asn": "AS13335", "prefix": "1.1.1.0/24", "maxLength": 24, "ta": "apnic"}]}"#
+        );
+        assert_eq!(vrps.validate(net("1.1.1.0/24"), 13335), RpkiState::Valid);
+        assert_eq!(vrps.validate(net("1.1.1.0/24"), 1), RpkiState::Invalid);
+    }
+
+    #[test]
+    fn load_rejects_invalid_files() {
+        for content in [
+            "ASN,IP Prefix,Max Length\nAS13335,1.1.1.0/24\n",
+            "ASN,IP Prefix,Max Length\nASX,1.1.1.0/24,24\n",
+            "ASN,IP Prefix,Max Length\nAS13335,1.1.1.0/33,24\n",
+            r#"{"roas": [{"asn": "13335x", "prefix": "1.1.1.0/24", "maxLength": 24}]}"#,
+        ] {
+            let mut file = NamedTempFile::new().unwrap();
+            file.write_all(content.as_bytes()).unwrap();
+            assert!(Vrps::load(file.path()).is_err(), "{}", content);
+        }
+    }
+
+    #[test]
+    fn parse_asn_formats() {
+        assert_eq!(parse_asn("AS13335").unwrap(), 13335);
+        assert_eq!(parse_asn("as13335").unwrap(), 13335);
+        assert_eq!(parse_asn(" 13335 ").unwrap(), 13335);
+        assert_eq!(parse_asn("AS4200000000").unwrap(), 4200000000);
+        assert!(parse_asn("AS").is_err());
+        assert!(parse_asn("AS-1").is_err());
+        assert!(parse_asn("AS4294967296").is_err());
+    }
+}
@@ -0,0 +1,347 @@
+use crate::{
+    cache::now_secs,
+    cidr::aggregate,
+    fetch_page,
+    i18n::tr,
+    parse_page,
+    models::PrefixRecord,
+    pipeline::Pipeline,
+    Source,
+    API_URL,
+};
+use std::{
+    collections::{ hash_map::DefaultHasher, HashMap, HashSet },
+    error::Error,
+    fmt::Write,
+    hash::{ Hash, Hasher },
+    net::{ IpAddr, SocketAddr },
+    sync::Arc,
+    time::Duration,
+};
+use axum::{
+    extract::{ Path, State },
+    http::{ header, HeaderMap, HeaderValue, StatusCode },
+    response::{ IntoResponse, Response },
+    routing::get,
+    Router,
+};
+use clap::Args as ClapArgs;
+use ipnetwork::IpNetwork;
+use serde_json::{ json, Map, Value };
+use tokio::sync::RwLock;
+use tracing::{ info, warn };
+
+/// serve 子命令：以HTTP接口提供ASN的前缀列表
+#[derive(ClapArgs, Debug, Clone)]
+pub struct ServeArgs {
+    /// 监听地址
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub listen: SocketAddr,
+
+    /// 后台重新下载已缓存ASN的间隔(秒)，必须大于0
+    #[arg(long, default_value_t = 3600, value_parser = clap::value_parser!(u64).range(1..))]
+    pub refresh_interval: u64,
+
+    /// 允许请求--as以外的任意ASN，第一次请求时下载；默认只提供--as指定的ASN
+    #[arg(long)]
+    pub allow_any_asn: bool,
+}
+
+// 一个ASN的一个CIDR版本，处理后的前缀记录
+struct Family {
+    header: Vec<String>,
+    records: Vec<PrefixRecord>,
+}
+
+// 内存中缓存的一个ASN
+struct AsEntry {
+    name: String,
+    updated_at: u64,
+    v4: Family,
+    v6: Family,
+}
+
+struct AppState {
+    source: Arc<Source>, // 使用-i指定的第一个来源
+    pipeline: Arc<Pipeline>,
+    // 可以请求的ASN；None表示允许任意ASN
+    allowed: Option<HashSet<u32>>,
+    entries: RwLock<HashMap<u32, Arc<AsEntry>>>,
+}
+
+impl AppState {
+    fn is_allowed(&self, asn: u32) -> bool {
+        self.allowed.as_ref().is_none_or(|allowed| allowed.contains(&asn))
+    }
+
+    // 下载并处理一个ASN的IPv4和IPv6前缀，两个版本在同一个页面中，只抓取一次
+    async fn load(&self, asn: u32) -> Result<AsEntry, Box<dyn Error>> {
+        let body = fetch_page(&self.source, 4, asn).await?;
+        let v4 = parse_page(&self.source, &body, 4)?;
+        let v6 = parse_page(&self.source, &body, 6)?;
+        // 只有IPv4或只有IPv6的ASN是正常的，两个版本都解析不到足够的前缀时才认为解析器已失效
+        if let (Err(e), Err(_)) = (self.pipeline.check_parsed(&v4, asn, 4), self.pipeline.check_parsed(&v6, asn, 6)) {
+            return Err(e.into());
+        }
+        let name = if v4.name.is_empty() { v6.name.clone() } else { v4.name.clone() };
+        let mut families = Vec::new();
+        for parsed in [v4, v6] {
+            let (header, records) = self.pipeline.process(parsed, asn);
+            families.push(Family { header, records });
+        }
+        let v6 = families.pop().unwrap();
+        let v4 = families.pop().unwrap();
+        Ok(AsEntry { name, updated_at: now_secs(), v4, v6 })
+    }
+
+    // 从缓存中取出，缓存中没有时立即下载
+    async fn get(&self, asn: u32) -> Result<Arc<AsEntry>, String> {
+        if let Some(entry) = self.entries.read().await.get(&asn) {
+            return Ok(entry.clone());
+        }
+        let entry = Arc::new(self.load(asn).await.map_err(|e| e.to_string())?);
+        self.entries.write().await.insert(asn, entry.clone());
+        Ok(entry)
+    }
+
+    // 后台重新下载所有已缓存的ASN，失败时保留原有数据
+    async fn refresh(&self) {
+        let asns: Vec<u32> = self.entries.read().await.keys().copied().collect();
+        for asn in asns {
+            let loaded = self.load(asn).await.map_err(|e| e.to_string());
+            match loaded {
+                Ok(entry) => {
+                    self.entries.write().await.insert(asn, Arc::new(entry));
+                }
+                Err(e) => {
+                    warn!(
+                        "{}",
+                        tr!(
+                            "AS{} ({}) 后台更新失败，继续使用原有数据：{}",
+                            "AS{} ({}) background refresh failed, keeping the previous data: {}",
+                            asn,
+                            API_URL[self.source.index],
+                            e
+                        )
+                    );
+                }
+            }
+        }
+    }
+}
+
+pub async fn serve(
+    args: &ServeArgs,
+    asns: &[u32],
+    source: Arc<Source>,
+    pipeline: Arc<Pipeline>
+) -> Result<(), Box<dyn Error>> {
+    if asns.is_empty() && !args.allow_any_asn {
+        return Err(
+            tr!("serve 需要用--as指定ASN，或者指定--allow-any-asn", "serve needs ASNs given by --as, or --allow-any-asn").into()
+        );
+    }
+    let allowed = if args.allow_any_asn { None } else { Some(asns.iter().copied().collect()) };
+    let state = Arc::new(AppState { source, pipeline, allowed, entries: RwLock::new(HashMap::new()) });
+
+    // 预先下载命令行中指定的ASN
+    for &asn in asns {
+        if let Err(e) = state.get(asn).await {
+            warn!("{}", tr!("AS{} 预先下载失败：{}", "AS{} initial download failed: {}", asn, e));
+        }
+    }
+
+    let interval = Duration::from_secs(args.refresh_interval);
+    let background = state.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            background.refresh().await;
+        }
+    });
+
+    let app = Router::new()
+        .route("/as/{file}", get(as_json))
+        .route("/as/{asn}/{file}", get(as_file))
+        .route("/lookup/{ip}", get(lookup))
+        .with_state(state);
+    let listener = tokio::net::TcpListener::bind(args.listen).await?;
+    info!("{}", tr!("HTTP服务已启动：http://{}", "HTTP server listening on http://{}", listener.local_addr()?));
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+// 按内容计算ETag；请求的If-None-Match相同时返回304
+fn respond(headers: &HeaderMap, content_type: &'static str, body: String) -> Response {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    let etag = format!("\"{:016x}\"", hasher.finish());
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+
+    let etag = HeaderValue::from_str(&etag).unwrap();
+    if not_modified {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+    (
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static(content_type)),
+            (header::ETAG, etag),
+        ],
+        body,
+    ).into_response()
+}
+
+// 路径中的ASN可以带AS前缀
+fn parse_asn(asn: &str) -> Option<u32> {
+    asn.trim_start_matches("AS").parse().ok()
+}
+
+fn invalid_asn(asn: &str) -> Response {
+    (StatusCode::BAD_REQUEST, tr!("无效的ASN：{}\n", "Invalid ASN: {}\n", asn)).into_response()
+}
+
+fn not_allowed(asn: u32) -> Response {
+    (
+        StatusCode::FORBIDDEN,
+        tr!("AS{} 不在--as指定的ASN中\n", "AS{} is not one of the ASNs given by --as\n", asn),
+    ).into_response()
+}
+
+fn family_json(family: &Family) -> Value {
+    let rows: Vec<Value> = family.records
+        .iter()
+        .map(|record| {
+            let mut row = Map::new();
+            for (column, value) in family.header.iter().zip(&record.row) {
+                row.insert(column.clone(), Value::String(value.clone()));
+            }
+            Value::Object(row)
+        })
+        .collect();
+    Value::Array(rows)
+}
+
+// GET /as/{asn}.json：两个CIDR版本的全部csv列
+async fn as_json(
+    State(state): State<Arc<AppState>>,
+    Path(file): Path<String>,
+    headers: HeaderMap
+) -> Response {
+    let Some(asn) = file.strip_suffix(".json") else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some(asn) = parse_asn(asn) else {
+        return invalid_asn(asn);
+    };
+    if !state.is_allowed(asn) {
+        return not_allowed(asn);
+    }
+    let entry = match state.get(asn).await {
+        Ok(entry) => entry,
+        Err(e) => {
+            return (StatusCode::BAD_GATEWAY, format!("{}\n", e)).into_response();
+        }
+    };
+    let body = json!({
+        "asn": asn,
+        "name": entry.name,
+        "source": API_URL[state.source.index],
+        "updated_at": entry.updated_at,
+        "ipv4": family_json(&entry.v4),
+        "ipv6": family_json(&entry.v6),
+    });
+    respond(&headers, "application/json", body.to_string())
+}
+
+// GET /as/{asn}/v4.txt、/as/{asn}/v6.txt：每行一个CIDR；/as/{asn}/nft：nftables的集合定义
+async fn as_file(
+    State(state): State<Arc<AppState>>,
+    Path((asn, file)): Path<(String, String)>,
+    headers: HeaderMap
+) -> Response {
+    if !matches!(file.as_str(), "v4.txt" | "v6.txt" | "nft") {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let Some(asn) = parse_asn(&asn) else {
+        return invalid_asn(&asn);
+    };
+    if !state.is_allowed(asn) {
+        return not_allowed(asn);
+    }
+    let entry = match state.get(asn).await {
+        Ok(entry) => entry,
+        Err(e) => {
+            return (StatusCode::BAD_GATEWAY, format!("{}\n", e)).into_response();
+        }
+    };
+
+    let mut body = String::new();
+    match file.as_str() {
+        "v4.txt" | "v6.txt" => {
+            let family = if file == "v4.txt" { &entry.v4 } else { &entry.v6 };
+            for prefix in state.pipeline.txt_prefixes(&family.records) {
+                writeln!(body, "{}", prefix).unwrap();
+            }
+        }
+        _ => {
+            // 区间集合中的元素不能重叠，总是聚合
+            for (family, version, addr_type) in [
+                (&entry.v4, 4, "ipv4_addr"),
+                (&entry.v6, 6, "ipv6_addr"),
+            ] {
+                let prefixes: Vec<IpNetwork> = family.records
+                    .iter()
+                    .map(|r| r.prefix)
+                    .collect();
+                let prefixes: Vec<String> = aggregate(&prefixes)
+                    .iter()
+                    .map(|p| p.to_string())
+                    .collect();
+                writeln!(body, "set AS{}_v{} {{", asn, version).unwrap();
+                writeln!(body, "\ttype {}", addr_type).unwrap();
+                writeln!(body, "\tflags interval").unwrap();
+                if !prefixes.is_empty() {
+                    writeln!(body, "\telements = {{ {} }}", prefixes.join(", ")).unwrap();
+                }
+                writeln!(body, "}}").unwrap();
+            }
+        }
+    }
+    respond(&headers, "text/plain; charset=utf-8", body)
+}
+
+// GET /lookup/{ip}：在已缓存的ASN中查找包含该IP的前缀，按前缀长度从长到短排列
+async fn lookup(
+    State(state): State<Arc<AppState>>,
+    Path(ip): Path<String>,
+    headers: HeaderMap
+) -> Response {
+    let ip: IpAddr = match ip.parse() {
+        Ok(ip) => ip,
+        Err(_) => {
+            return (StatusCode::BAD_REQUEST, tr!("无效的IP地址：{}\n", "Invalid IP address: {}\n", ip)).into_response();
+        }
+    };
+
+    let mut matches = Vec::new();
+    for (&asn, entry) in state.entries.read().await.iter() {
+        for family in [&entry.v4, &entry.v6] {
+            for record in &family.records {
+                if record.prefix.contains(ip) {
+                    matches.push((record.prefix.prefix(), asn, entry.name.clone(), record.prefix));
+                }
+            }
+        }
+    }
+    matches.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    let matches: Vec<Value> = matches
+        .into_iter()
+        .map(|(_, asn, name, prefix)| json!({ "asn": asn, "name": name, "prefix": prefix.to_string() }))
+        .collect();
+    let body = json!({ "ip": ip.to_string(), "matches": matches });
+    respond(&headers, "application/json", body.to_string())
+}
@@ -0,0 +1,172 @@
+use crate::{
+    cidr::address_count,
+    fetch_page,
+    i18n::tr,
+    parse_page,
+    pipeline::{ country_index, Pipeline },
+    Source,
+    API_URL,
+};
+use std::{ collections::BTreeMap, error::Error, sync::Arc };
+use clap::Args as ClapArgs;
+use ipnetwork::IpNetwork;
+use serde::Serialize;
+
+/// stats 子命令：统计ASN的地址空间大小
+#[derive(ClapArgs, Debug, Clone)]
+pub struct StatsArgs {
+    /// 输出JSON而不是表格
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct CountryStats {
+    prefixes: usize,
+    addresses: u128,
+}
+
+// 一个ASN的一个CIDR版本的统计
+#[derive(Debug, Serialize)]
+struct FamilyStats {
+    asn: u32,
+    name: String,
+    source: &'static str,
+    family: u8,
+    prefixes: usize,
+    addresses: u128, // 去掉重叠部分后的地址总数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slash24: Option<f64>, // IPv4：相当于多少个/24
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slash48: Option<f64>, // IPv6：相当于多少个/48
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slash32: Option<f64>, // IPv6：相当于多少个/32
+    by_length: BTreeMap<u8, usize>,
+    by_country: BTreeMap<String, CountryStats>, // 来源没有国家代码时为空
+}
+
+// 地址数相当于多少个/len
+fn equivalents(addresses: u128, bits: u32, len: u32) -> f64 {
+    (addresses as f64) / (2f64).powi((bits - len) as i32)
+}
+
+fn family_stats(
+    header: &[String],
+    prefixes: &[(IpNetwork, Option<String>)],
+    asn: u32,
+    name: String,
+    source: &'static str,
+    family: u8
+) -> FamilyStats {
+    let networks: Vec<IpNetwork> = prefixes
+        .iter()
+        .map(|(p, _)| *p)
+        .collect();
+    let addresses = address_count(&networks);
+
+    let mut by_length = BTreeMap::new();
+    for prefix in &networks {
+        *by_length.entry(prefix.prefix()).or_insert(0) += 1;
+    }
+
+    let mut by_country = BTreeMap::new();
+    if header.iter().any(|h| h == "country_code") {
+        let mut grouped: BTreeMap<String, Vec<IpNetwork>> = BTreeMap::new();
+        for (prefix, country) in prefixes {
+            let country = country.clone().filter(|c| !c.is_empty()).unwrap_or_else(|| "-".to_string());
+            grouped.entry(country).or_default().push(*prefix);
+        }
+        for (country, networks) in grouped {
+            by_country.insert(country, CountryStats {
+                prefixes: networks.len(),
+                addresses: address_count(&networks),
+            });
+        }
+    }
+
+    let (slash24, slash48, slash32) = match family {
+        4 => (Some(equivalents(addresses, 32, 24)), None, None),
+        _ => (None, Some(equivalents(addresses, 128, 48)), Some(equivalents(addresses, 128, 32))),
+    };
+    FamilyStats {
+        asn,
+        name,
+        source,
+        family,
+        prefixes: networks.len(),
+        addresses,
+        slash24,
+        slash48,
+        slash32,
+        by_length,
+        by_country,
+    }
+}
+
+fn print_table(stats: &FamilyStats) {
+    println!("AS{} {} ({}) IPv{}", stats.asn, stats.name, stats.source, stats.family);
+    println!("  {}", tr!("前缀数：{}", "Prefixes: {}", stats.prefixes));
+    println!("  {}", tr!("地址总数(去重后)：{}", "Unique addresses: {}", stats.addresses));
+    if let Some(n) = stats.slash24 {
+        println!("  {}", tr!("相当于 /24 的数量：{:.2}", "/24 equivalents: {:.2}", n));
+    }
+    if let (Some(n48), Some(n32)) = (stats.slash48, stats.slash32) {
+        println!("  {}", tr!("相当于 /48 的数量：{:.2}", "/48 equivalents: {:.2}", n48));
+        println!("  {}", tr!("相当于 /32 的数量：{:.4}", "/32 equivalents: {:.4}", n32));
+    }
+    println!("  {}", tr!("按前缀长度：", "By prefix length:"));
+    for (len, count) in &stats.by_length {
+        println!("    /{:<4}{:>8}", len, count);
+    }
+    if !stats.by_country.is_empty() {
+        println!("  {}", tr!("按国家代码：", "By country code:"));
+        for (country, c) in &stats.by_country {
+            println!(
+                "    {:<4}{}",
+                country,
+                tr!("{} 个前缀，{} 个地址", "{} prefixes, {} addresses", c.prefixes, c.addresses)
+            );
+        }
+    }
+}
+
+// 下载并统计每个来源、每个ASN、每个CIDR版本的前缀
+pub async fn stats(
+    args: &StatsArgs,
+    asns: &[u32],
+    versions: &[u8],
+    sources: &[Arc<Source>],
+    pipeline: &Pipeline
+) -> Result<(), Box<dyn Error>> {
+    let mut all = Vec::new();
+    for source in sources {
+        for &asn in asns {
+            // 同一个页面中同时包含IPv4和IPv6的前缀，每个ASN只抓取一次
+            let Some(&first) = versions.first() else {
+                continue;
+            };
+            let body = fetch_page(source, first, asn).await?;
+            for &version in versions {
+                let parsed = parse_page(source, &body, version)?;
+                pipeline.check_parsed(&parsed, asn, version)?;
+                let name = parsed.name.clone();
+                let (header, records) = pipeline.process(parsed, asn);
+                let country = country_index(&header);
+                let prefixes: Vec<(IpNetwork, Option<String>)> = records
+                    .into_iter()
+                    .map(|r| (r.prefix, country.and_then(|i| r.row.get(i).cloned())))
+                    .collect();
+                all.push(family_stats(&header, &prefixes, asn, name, API_URL[source.index], version));
+            }
+        }
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&all)?);
+    } else {
+        for stats in &all {
+            print_table(stats);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use crate::i18n::tr;
+use std::{ error::Error, time::Duration };
+use clap::Args as ClapArgs;
+use rand::Rng;
+use tokio::process::Command;
+
+/// watch 子命令：常驻运行，按间隔重新下载，内容变化时才重写文件并执行钩子命令
+#[derive(ClapArgs, Debug, Clone)]
+pub struct WatchArgs {
+    /// 两次下载之间的间隔(秒)，必须大于0
+    #[arg(long, default_value_t = 3600, value_parser = clap::value_parser!(u64).range(1..))]
+    pub interval: u64,
+
+    /// 在间隔上随机增加的时间上限(秒)，避免多台机器同时请求
+    #[arg(long, default_value_t = 60)]
+    pub jitter: u64,
+
+    /// 有文件内容变化时执行的命令，例如 "nft -f /etc/nftables.d/as.nft" 或 "systemctl reload xxx"
+    #[arg(long)]
+    pub hook: Option<String>,
+}
+
+impl WatchArgs {
+    // 下一次下载前等待的时间：间隔加上随机抖动
+    pub fn next_delay(&self) -> Duration {
+        let jitter = if self.jitter > 0 { rand::thread_rng().gen_range(0..=self.jitter) } else { 0 };
+        Duration::from_secs(self.interval + jitter)
+    }
+}
+
+// 通过shell执行钩子命令，命令失败只报告不退出
+pub async fn run_hook(hook: &str) -> Result<(), Box<dyn Error>> {
+    let status = if cfg!(windows) {
+        Command::new("cmd").args(["/C", hook]).status().await?
+    } else {
+        Command::new("sh").args(["-c", hook]).status().await?
+    };
+    if !status.success() {
+        return Err(tr!("钩子命令 {} 执行失败：{}", "Hook command {} failed: {}", hook, status).into());
+    }
+    Ok(())
+}
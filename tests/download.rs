@@ -0,0 +1,1031 @@
+use std::{ fs, path::Path, process::Output };
+use ipnetwork::IpNetwork;
+use tempfile::TempDir;
+use tokio::process::Command;
+use wiremock::{ matchers::{ header, header_exists, method, path }, Mock, MockServer, ResponseTemplate };
+
+fn fixture(name: &str) -> String {
+    fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)).unwrap()
+}
+
+// 在临时目录中运行的程序，输出文件都写在该目录下；去掉语言环境变量，使用默认的中文
+fn command(dir: &TempDir) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_download_as_cidrs"));
+    command.current_dir(dir.path()).env_remove("LC_ALL").env_remove("LC_MESSAGES").env_remove("LANG");
+    command
+}
+
+async fn run(dir: &TempDir, args: &[&str]) -> Output {
+    command(dir).args(args).args(["--retries", "0"]).output().await.unwrap()
+}
+
+fn read(dir: &TempDir, file: &str) -> String {
+    fs::read_to_string(dir.path().join(file)).unwrap()
+}
+
+// 只包含指定前缀的bgp.tools页面，保存到dir中，返回文件路径
+fn bgp_tools_page(dir: &TempDir, prefixes: &[&str]) -> String {
+    let rows: String = prefixes
+        .iter()
+        .map(|p| format!("<tr><td><img title=\"US\" alt=\"US\"></td><td><a href=\"/prefix/{0}\">{0}</a></td><td>Test</td></tr>\n", p))
+        .collect();
+    let page = format!(
+        "<html><head><title>AS64500 Test - bgp.tools</title></head><body>\n\
+         <table id=\"fancytable\"><thead><tr><th>Country</th><th>Prefix</th><th>Description</th></tr></thead>\n\
+         <tbody id=\"donotscrapebgptools-prefixlist-tbody\">\n{}</tbody></table></body></html>\n",
+        rows
+    );
+    let path = dir.path().join("page.html");
+    fs::write(&path, page).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+async fn serve(route: &str, response: ResponseTemplate) -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("GET")).and(path(route)).respond_with(response).mount(&server).await;
+    server
+}
+
+#[tokio::test]
+async fn bgpview_v4() {
+    let server = serve(
+        "/asn/13335/prefixes",
+        ResponseTemplate::new(200).set_body_string(fixture("bgpview_prefixes.json"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let output = run(&dir, &["--as", "13335", "-i", "0", "--base-url", &server.uri()]).await;
+
+    assert!(output.status.success());
+    assert_eq!(
+        read(&dir, "api.bgpview.io/AS13335_v4.csv"),
+        "IP地址前缀,国家代码,名称,描述,rir名称\n\
+         1.1.1.0/24,AU,APNIC-LABS,APNIC and Cloudflare DNS Resolver project,APNIC\n\
+         104.16.0.0/13,,,,\n"
+    );
+    assert_eq!(read(&dir, "api.bgpview.io/AS13335_v4.txt"), "1.1.1.0/24\n104.16.0.0/13\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not-a-prefix"));
+}
+
+#[tokio::test]
+async fn bgpview_v6() {
+    let server = serve(
+        "/asn/13335/prefixes",
+        ResponseTemplate::new(200).set_body_string(fixture("bgpview_prefixes.json"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let output = run(&dir, &["--as", "13335", "-c", "6", "-i", "0", "--base-url", &server.uri()]).await;
+
+    assert!(output.status.success());
+    assert_eq!(
+        read(&dir, "api.bgpview.io/AS13335_v6.csv"),
+        "IP地址前缀,国家代码,名称,描述,rir名称\n\
+         2606:4700::/32,US,CLOUDFLARENET,\"Cloudflare, Inc.\",ARIN\n"
+    );
+    assert_eq!(read(&dir, "api.bgpview.io/AS13335_v6.txt"), "2606:4700::/32\n");
+}
+
+#[tokio::test]
+async fn bgpview_status_not_ok() {
+    let server = serve(
+        "/asn/13335/prefixes",
+        ResponseTemplate::new(200).set_body_string(fixture("bgpview_error.json"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let output = run(&dir, &["--as", "13335", "-i", "0", "--base-url", &server.uri()]).await;
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("获取到的数据状态不是ok，而是error"));
+    assert!(!dir.path().join("api.bgpview.io/AS13335_v4.txt").exists());
+}
+
+#[tokio::test]
+async fn bgp_he_net_v4() {
+    let server = serve(
+        "/AS13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_he_net.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let output = run(&dir, &["--as", "13335", "-i", "1", "--base-url", &server.uri()]).await;
+
+    assert!(output.status.success());
+    assert_eq!(
+        read(&dir, "bgp.he.net/AS13335_v4.csv"),
+        "IP地址前缀,国家代码,国家名称,描述\n\
+         1.1.1.0/24,AU,Australia,APNIC and Cloudflare DNS Resolver project\n\
+         104.16.0.0/13,US,United States,\"Cloudflare, Inc.\"\n"
+    );
+    assert_eq!(read(&dir, "bgp.he.net/AS13335_v4.txt"), "1.1.1.0/24\n104.16.0.0/13\n");
+}
+
+// 没有国旗的行国家代码和国家名称为空，与表头列数不一致的行被跳过并报告
+#[tokio::test]
+async fn bgp_he_net_row_without_flag() {
+    let server = serve(
+        "/AS13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_he_net_no_flag.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let output = run(&dir, &["--as", "13335", "-i", "1", "--base-url", &server.uri()]).await;
+
+    assert!(output.status.success());
+    assert_eq!(
+        read(&dir, "bgp.he.net/AS13335_v4.csv"),
+        "IP地址前缀,国家代码,国家名称,描述\n\
+         1.1.1.0/24,AU,Australia,APNIC and Cloudflare DNS Resolver project\n\
+         103.21.244.0/24,,,\"Cloudflare, Inc.\"\n\
+         104.16.0.0/13,US,United States,\"Cloudflare, Inc.\"\n"
+    );
+    // 列数与表头不一致的行被跳过并报告
+    assert!(String::from_utf8_lossy(&output.stderr).contains("表格行有 1 列，与表头的 2 列不一致，已跳过：198.41.128.0/17"));
+}
+
+#[tokio::test]
+async fn bgp_he_net_v6() {
+    let server = serve(
+        "/AS13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_he_net.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let output = run(&dir, &["--as", "13335", "-c", "6", "-i", "1", "--base-url", &server.uri()]).await;
+
+    assert!(output.status.success());
+    assert_eq!(
+        read(&dir, "bgp.he.net/AS13335_v6.csv"),
+        "IP地址前缀,国家代码,国家名称,描述\n\
+         2606:4700::/32,US,United States,\"Cloudflare, Inc.\"\n"
+    );
+    assert_eq!(read(&dir, "bgp.he.net/AS13335_v6.txt"), "2606:4700::/32\n");
+}
+
+#[tokio::test]
+async fn bgp_he_net_not_found() {
+    let server = serve("/AS13335", ResponseTemplate::new(404)).await;
+    let dir = TempDir::new().unwrap();
+    let output = run(&dir, &["--as", "13335", "-i", "1", "--base-url", &server.uri()]).await;
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("状态码是: 404 Not Found"));
+    assert!(!dir.path().join("bgp.he.net/AS13335_v4.txt").exists());
+}
+
+#[tokio::test]
+async fn bgp_tools_v4() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let output = run(&dir, &["--as", "13335", "-i", "2", "--base-url", &server.uri()]).await;
+
+    assert!(output.status.success());
+    assert_eq!(
+        read(&dir, "bgp.tools/AS13335_v4.csv"),
+        "IP地址前缀,国家代码,描述\n\
+         104.16.0.0/13,US,\"Cloudflare, Inc.\"\n\
+         1.1.1.0/24,AU,APNIC and Cloudflare DNS Resolver project\n"
+    );
+    assert_eq!(read(&dir, "bgp.tools/AS13335_v4.txt"), "104.16.0.0/13\n1.1.1.0/24\n");
+}
+
+#[tokio::test]
+async fn bgp_tools_missing_table() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools_no_table.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let output = run(&dir, &["--as", "13335", "-i", "2", "--base-url", &server.uri()]).await;
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("parser likely broken"));
+    assert!(!dir.path().join("bgp.tools/AS13335_v4.csv").exists());
+    assert!(!dir.path().join("bgp.tools/AS13335_v4.txt").exists());
+}
+
+#[tokio::test]
+async fn retries_server_errors() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/as/13335"))
+        .respond_with(ResponseTemplate::new(503).insert_header("Retry-After", "0"))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server).await;
+    Mock::given(method("GET"))
+        .and(path("/as/13335"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html")))
+        .mount(&server).await;
+    let dir = TempDir::new().unwrap();
+    let output = command(&dir)
+        .args(["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--retries", "1"])
+        .output().await
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("返回状态码 503"));
+    assert_eq!(read(&dir, "bgp.tools/AS13335_v4.txt"), "104.16.0.0/13\n1.1.1.0/24\n");
+}
+
+#[tokio::test]
+async fn gives_up_on_long_retry_after() {
+    let server = serve("/as/13335", ResponseTemplate::new(503).insert_header("Retry-After", "86400")).await;
+    let dir = TempDir::new().unwrap();
+    let output = tokio::time
+        ::timeout(
+            std::time::Duration::from_secs(20),
+            command(&dir).args(["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--retries", "3"]).output()
+        ).await
+        .expect("Retry-After 86400 should not stall the download")
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("要求86400秒后重试，超过60秒，不再重试"));
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn per_source_rate_limits() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let output = run(&dir, &["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--rate", "0"]).await;
+    assert!(String::from_utf8_lossy(&output.stderr).contains("值必须大于0"));
+    assert!(!dir.path().join("bgp.tools").exists());
+
+    // 太小的速率会让等待时间溢出，无穷大也不接受
+    for rate in ["1e-30", "bgp.tools=inf"] {
+        let output = run(&dir, &["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--rate", rate]).await;
+        assert!(String::from_utf8_lossy(&output.stderr).contains("每秒请求数必须是不小于0.001的有限数"), "{}", rate);
+    }
+    assert!(server.received_requests().await.unwrap().is_empty());
+
+    let output = run(&dir, &["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--rate", "example.com=1"]).await;
+    assert!(String::from_utf8_lossy(&output.stderr).contains("未知的来源：example.com"));
+
+    let output = run(
+        &dir,
+        &["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--rate", "1,bgp.tools=5", "--max-connections", "bgp.tools=2"]
+    ).await;
+    assert!(output.status.success());
+    assert_eq!(read(&dir, "bgp.tools/AS13335_v4.txt"), "104.16.0.0/13\n1.1.1.0/24\n");
+}
+
+#[tokio::test]
+async fn response_cache() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/as/13335"))
+        .and(header("If-None-Match", "\"v1\""))
+        .and(header_exists("If-Modified-Since"))
+        .respond_with(ResponseTemplate::new(304))
+        .with_priority(1)
+        .mount(&server).await;
+    Mock::given(method("GET"))
+        .and(path("/as/13335"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("ETag", "\"v1\"")
+                .insert_header("Last-Modified", "Wed, 21 Oct 2015 07:28:00 GMT")
+                .set_body_string(fixture("bgp_tools.html"))
+        )
+        .mount(&server).await;
+    let dir = TempDir::new().unwrap();
+    let common = ["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--cache-dir", "cache"];
+    let expected = "104.16.0.0/13\n1.1.1.0/24\n";
+
+    // 第一次抓取后写入缓存，有效期内再次运行不发出请求
+    let output = run(&dir, &common).await;
+    assert!(output.status.success());
+    assert!(dir.path().join("cache/bgp.tools/AS13335.body").exists());
+    let output = run(&dir, &common).await;
+    assert!(output.status.success());
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+
+    // 过期后带上条件请求头重新验证，304时继续使用缓存
+    fs::remove_file(dir.path().join("bgp.tools/AS13335_v4.txt")).unwrap();
+    let output = run(&dir, &[&common[..], &["--cache-ttl", "0"]].concat()).await;
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("内容未变化，使用缓存"));
+    assert_eq!(read(&dir, "bgp.tools/AS13335_v4.txt"), expected);
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].headers["if-none-match"], "\"v1\"");
+    assert_eq!(requests[1].headers["if-modified-since"], "Wed, 21 Oct 2015 07:28:00 GMT");
+
+    // --refresh 忽略缓存，不带条件请求头
+    let output = run(&dir, &[&common[..], &["--refresh"]].concat()).await;
+    assert!(output.status.success());
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 3);
+    assert!(!requests[2].headers.contains_key("if-none-match"));
+
+    // --offline 只使用缓存，即使已经过期
+    fs::remove_file(dir.path().join("bgp.tools/AS13335_v4.txt")).unwrap();
+    let output = run(&dir, &[&common[..], &["--offline", "--cache-ttl", "0"]].concat()).await;
+    assert!(output.status.success());
+    assert_eq!(read(&dir, "bgp.tools/AS13335_v4.txt"), expected);
+    assert_eq!(server.received_requests().await.unwrap().len(), 3);
+
+    // 没有缓存时离线模式报错，也不发出请求
+    let output = run(&dir, &["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--cache-dir", "empty", "--offline"]).await;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("离线模式下没有 bgp.tools AS13335 的缓存"));
+    assert_eq!(server.received_requests().await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn base_url_from_env() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let output = command(&dir)
+        .args(["--as", "13335", "-i", "2", "--retries", "0"])
+        .env("BGP_TOOLS_URL", server.uri())
+        .output().await
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(read(&dir, "bgp.tools/AS13335_v4.txt"), "104.16.0.0/13\n1.1.1.0/24\n");
+}
+
+#[tokio::test]
+async fn base_url_from_config_file() {
+    let server = serve(
+        "/AS13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_he_net.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    fs::write(
+        dir.path().join("download_as_cidrs.toml"),
+        format!("[sources]\n\"bgp.he.net\" = \"{}/\"\n", server.uri())
+    ).unwrap();
+    let output = run(&dir, &["--as", "13335", "-i", "1"]).await;
+
+    assert!(output.status.success());
+    assert_eq!(read(&dir, "bgp.he.net/AS13335_v4.txt"), "1.1.1.0/24\n104.16.0.0/13\n");
+}
+
+fn write_profile(dir: &TempDir, server: &MockServer) {
+    fs::write(
+        dir.path().join("download_as_cidrs.toml"),
+        format!(
+            "[sources]\n\
+             \"bgp.tools\" = \"{}\"\n\
+             \n\
+             [defaults]\n\
+             retries = 0\n\
+             \n\
+             [profiles.cloudflare-edge]\n\
+             as = [13335]\n\
+             source = [2]\n\
+             cidr-version = [4, 6]\n\
+             aggregate = true\n\
+             format = [\"txt\"]\n",
+            server.uri()
+        )
+    ).unwrap();
+}
+
+#[tokio::test]
+async fn profile_from_config_file() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    write_profile(&dir, &server);
+    let output = run(&dir, &["--profile", "cloudflare-edge"]).await;
+
+    assert!(output.status.success());
+    assert_eq!(read(&dir, "bgp.tools/AS13335_v4.txt"), "1.1.1.0/24\n104.16.0.0/13\n");
+    assert_eq!(read(&dir, "bgp.tools/AS13335_v6.txt"), "2606:4700::/32\n");
+    assert!(!dir.path().join("bgp.tools/AS13335_v4.csv").exists());
+}
+
+#[tokio::test]
+async fn command_line_overrides_profile() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    write_profile(&dir, &server);
+    let output = run(&dir, &["--profile", "cloudflare-edge", "-c", "6", "--format", "csv"]).await;
+
+    assert!(output.status.success());
+    assert!(dir.path().join("bgp.tools/AS13335_v6.csv").exists());
+    assert!(!dir.path().join("bgp.tools/AS13335_v6.txt").exists());
+    assert!(!dir.path().join("bgp.tools/AS13335_v4.csv").exists());
+}
+
+#[tokio::test]
+async fn invalid_filter_files() {
+    let dir = TempDir::new().unwrap();
+    let page = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/bgp_tools.html");
+    let common = ["--as", "13335", "-i", "2", "--from-file", page.to_str().unwrap()];
+
+    // 写错的CIDR会被当作文件路径，报错时要说明两种都不是
+    let output = run(&dir, &[&common[..], &["--exclude", "1.1.1.0/33"]].concat()).await;
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--exclude 的 1.1.1.0/33 既不是有效的CIDR"));
+
+    let output = run(&dir, &[&common[..], &["--vrp", "missing.csv"]].concat()).await;
+    assert!(String::from_utf8_lossy(&output.stderr).contains("无法读取VRP文件 missing.csv"));
+
+    let output = run(&dir, &[&common[..], &["--drop-bogons", "--bogon-file", "missing.txt"]].concat()).await;
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--bogon-file 无效：无法读取文件 missing.txt"));
+    assert!(!dir.path().join("bgp.tools").exists());
+}
+
+#[tokio::test]
+async fn normalize_host_bits() {
+    let dir = TempDir::new().unwrap();
+    let page = bgp_tools_page(&dir, &["1.2.3.4/24"]);
+    let output = run(&dir, &["--as", "64500", "-i", "2", "--from-file", &page]).await;
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("前缀带有主机位，已规范化：1.2.3.4/24 -> 1.2.3.0/24"));
+    assert_eq!(read(&dir, "bgp.tools/AS64500_v4.csv"), "IP地址前缀,国家代码,描述\n1.2.3.0/24,US,Test\n");
+    assert_eq!(read(&dir, "bgp.tools/AS64500_v4.txt"), "1.2.3.0/24\n");
+}
+
+#[tokio::test]
+async fn drop_bogons() {
+    let dir = TempDir::new().unwrap();
+    let page = bgp_tools_page(&dir, &["1.1.1.0/24", "10.1.0.0/16", "100.0.0.0/8", "203.0.114.0/24"]);
+    let common = ["--as", "64500", "-i", "2", "--from-file", &page, "--drop-bogons", "-o", "-", "--format", "txt"];
+
+    // 位于保留地址段内的前缀整个去掉，包含保留地址段的前缀只减去 100.64.0.0/10
+    let output = run(&dir, &common).await;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "1.1.1.0/24\n100.0.0.0/10\n100.128.0.0/9\n203.0.114.0/24\n"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("位于保留地址段 10.0.0.0/8 内，已去掉"));
+    assert!(stderr.contains("100.0.0.0/8 包含保留地址段 100.64.0.0/10，剩余：100.0.0.0/10, 100.128.0.0/9"));
+
+    // --bogon-file 中的地址段同样去掉
+    fs::write(dir.path().join("bogons.txt"), "# 额外的保留地址\n203.0.114.0/23\n").unwrap();
+    let output = run(&dir, &[&common[..], &["--bogon-file", "bogons.txt"]].concat()).await;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1.1.1.0/24\n100.0.0.0/10\n100.128.0.0/9\n");
+}
+
+#[tokio::test]
+async fn rpki_state_of_excluded_remainders() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("vrp.csv"), "ASN,IP Prefix,Max Length,Trust Anchor\nAS13335,1.1.1.0/24,24,apnic\n").unwrap();
+    let page = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/bgp_tools.html");
+    let page = page.to_str().unwrap();
+    let common = ["--as", "13335", "-i", "2", "--from-file", page, "--vrp", "vrp.csv", "--exclude", "1.1.1.0/26", "-o", "-", "--format", "csv"];
+
+    // 剩余部分沿用原来宣告的1.1.1.0/24的验证状态
+    let output = run(&dir, &common).await;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "IP地址前缀,国家代码,描述,RPKI状态\n\
+         104.16.0.0/13,US,\"Cloudflare, Inc.\",NotFound\n\
+         1.1.1.64/26,AU,APNIC and Cloudflare DNS Resolver project,Valid\n\
+         1.1.1.128/25,AU,APNIC and Cloudflare DNS Resolver project,Valid\n"
+    );
+
+    // 长度限制作用于拆分后的剩余部分
+    let output = run(&dir, &[&common[..], &["--rpki", "valid-only", "--max-len-v4", "25"]].concat()).await;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "IP地址前缀,国家代码,描述,RPKI状态\n1.1.1.128/25,AU,APNIC and Cloudflare DNS Resolver project,Valid\n"
+    );
+}
+
+#[tokio::test]
+async fn invert_prefix_lists() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let parse = |content: String| -> Vec<IpNetwork> { content.lines().map(|l| l.parse().unwrap()).collect() };
+    let overlaps = |a: &IpNetwork, b: &IpNetwork| a.contains(b.network()) || b.contains(a.network());
+    let announced: Vec<IpNetwork> = ["104.16.0.0/13", "1.1.1.0/24"].iter().map(|s| s.parse().unwrap()).collect();
+    let private: Vec<IpNetwork> = ["10.0.0.0/8", "172.16.0.0/12", "192.168.0.0/16"].iter().map(|s| s.parse().unwrap()).collect();
+
+    let output = run(&dir, &["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--invert"]).await;
+    assert!(output.status.success());
+    let invert = parse(read(&dir, "bgp.tools/AS13335_v4_invert.txt"));
+    assert_eq!(invert[..3], parse("0.0.0.0/8\n1.0.0.0/16\n1.1.0.0/24\n".to_string())[..]);
+    assert!(invert.iter().all(|n| announced.iter().all(|a| !overlaps(n, a))));
+    // 反选列表与下载到的前缀合起来正好是全部IPv4地址
+    let total: u64 = invert.iter().map(|n| 1u64 << (32 - n.prefix())).sum();
+    assert_eq!(total, (1u64 << 32) - 524544);
+    assert!(invert.iter().any(|n| n.contains("10.1.2.3".parse().unwrap())));
+
+    let output = run(&dir, &["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--invert", "--invert-exclude-private"]).await;
+    assert!(output.status.success());
+    let invert = parse(read(&dir, "bgp.tools/AS13335_v4_invert.txt"));
+    assert!(invert.iter().all(|n| announced.iter().chain(&private).all(|a| !overlaps(n, a))));
+    assert!(invert.iter().any(|n| n.contains("8.8.8.8".parse().unwrap())));
+}
+
+#[tokio::test]
+async fn aggregate_txt_only_both_versions() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let output = run(
+        &dir,
+        &["--as", "13335", "-i", "2", "-c", "4,6", "--format", "txt", "--aggregate", "--base-url", &server.uri()]
+    ).await;
+
+    assert!(output.status.success());
+    // 聚合后按地址排序
+    assert_eq!(read(&dir, "bgp.tools/AS13335_v4.txt"), "1.1.1.0/24\n104.16.0.0/13\n");
+    assert_eq!(read(&dir, "bgp.tools/AS13335_v6.txt"), "2606:4700::/32\n");
+    assert!(!dir.path().join("bgp.tools/AS13335_v4.csv").exists());
+    assert!(!dir.path().join("bgp.tools/AS13335_v6.csv").exists());
+    // 两个版本在同一个页面中，只请求一次
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn stdout_with_several_tasks() {
+    let dir = TempDir::new().unwrap();
+    let page = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/bgp_tools.html");
+    let common = ["--as", "13335", "-i", "2", "--from-file", page.to_str().unwrap(), "-c", "4,6", "-o", "-"];
+
+    // 每个任务都有自己的表头，拼在一起不是合法的csv
+    let output = run(&dir, &[&common[..], &["--format", "csv"]].concat()).await;
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("-o - 输出csv时只能有一个ASN、一个来源和一个CIDR版本"));
+
+    let output = run(&dir, &[&common[..], &["--format", "txt"]].concat()).await;
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("1.1.1.0/24\n"));
+    assert!(stdout.contains("2606:4700::/32\n"));
+}
+
+#[tokio::test]
+async fn output_dir_with_name_template() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let output = run(
+        &dir,
+        &[
+            "--as",
+            "13335",
+            "-i",
+            "2",
+            "--base-url",
+            &server.uri(),
+            "--output-dir",
+            "out",
+            "--name-template",
+            "{source}-AS{asn}-v{family}",
+        ]
+    ).await;
+
+    assert!(output.status.success());
+    assert_eq!(read(&dir, "out/bgp.tools-AS13335-v4.txt"), "104.16.0.0/13\n1.1.1.0/24\n");
+    assert!(dir.path().join("out/bgp.tools-AS13335-v4.csv").exists());
+}
+
+#[tokio::test]
+async fn output_to_stdout() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let output = run(&dir, &["--as", "13335", "-i", "2", "--base-url", &server.uri(), "-o", "-"]).await;
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "104.16.0.0/13\n1.1.1.0/24\n");
+    assert!(!dir.path().join("bgp.tools").exists());
+}
+
+#[tokio::test]
+async fn split_and_filter_by_country() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let output = run(&dir, &["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--split-by", "country"]).await;
+
+    assert!(output.status.success());
+    assert_eq!(read(&dir, "bgp.tools/AS13335_v4_US.txt"), "104.16.0.0/13\n");
+    assert_eq!(
+        read(&dir, "bgp.tools/AS13335_v4_AU.csv"),
+        "IP地址前缀,国家代码,描述\n1.1.1.0/24,AU,APNIC and Cloudflare DNS Resolver project\n"
+    );
+    assert!(!dir.path().join("bgp.tools/AS13335_v4.txt").exists());
+
+    let output = run(&dir, &["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--country", "au,cn", "-o", "-"]).await;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1.1.1.0/24\n");
+}
+
+#[tokio::test]
+async fn keep_previous_file_on_shrink() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let previous = "1.0.0.0/24\n1.0.1.0/24\n1.0.2.0/24\n1.0.3.0/24\n";
+    fs::create_dir(dir.path().join("bgp.tools")).unwrap();
+    fs::write(dir.path().join("bgp.tools/AS13335_v4.txt"), previous).unwrap();
+    let args = ["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--format", "txt"];
+
+    let mut shrink = args.to_vec();
+    shrink.extend(["--keep-on-shrink", "40"]);
+    let output = run(&dir, &shrink).await;
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("从 4 减少到 2，超过 40%"));
+    assert_eq!(read(&dir, "bgp.tools/AS13335_v4.txt"), previous);
+
+    let output = run(&dir, &args).await;
+    assert!(output.status.success());
+    assert_eq!(read(&dir, "bgp.tools/AS13335_v4.txt"), "104.16.0.0/13\n1.1.1.0/24\n");
+    let leftovers = fs::read_dir(dir.path().join("bgp.tools")).unwrap().count();
+    assert_eq!(leftovers, 1);
+}
+
+#[tokio::test]
+async fn fails_on_large_prefix_drop() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let previous = "1.0.0.0/24\n1.0.1.0/24\n1.0.2.0/24\n1.0.3.0/24\n";
+    fs::create_dir(dir.path().join("bgp.tools")).unwrap();
+    fs::write(dir.path().join("bgp.tools/AS13335_v4.txt"), previous).unwrap();
+    let output = run(
+        &dir,
+        &["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--max-drop", "25"]
+    ).await;
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("前缀数从 4 减少到 2，超过 --max-drop 25%"));
+    assert!(stderr.contains("parser likely broken"));
+    assert_eq!(read(&dir, "bgp.tools/AS13335_v4.txt"), previous);
+}
+
+#[tokio::test]
+async fn watch_runs_hook_only_on_change() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let mut child = command(&dir)
+        .args(["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--retries", "0"])
+        .args(["watch", "--interval", "1", "--jitter", "0", "--hook", "echo changed >> hook.log"])
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(3500)).await;
+    child.kill().await.unwrap();
+
+    assert!(server.received_requests().await.unwrap().len() >= 2);
+    assert_eq!(read(&dir, "bgp.tools/AS13335_v4.txt"), "104.16.0.0/13\n1.1.1.0/24\n");
+    assert_eq!(read(&dir, "hook.log"), "changed\n");
+
+    // 间隔为0时不启动，避免不停地请求来源网站
+    let requests = server.received_requests().await.unwrap().len();
+    let output = command(&dir)
+        .args(["--as", "13335", "-i", "2", "--base-url", &server.uri()])
+        .args(["watch", "--interval", "0"])
+        .output().await
+        .unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--interval"));
+    assert_eq!(server.received_requests().await.unwrap().len(), requests);
+}
+
+#[tokio::test]
+async fn serve_prefix_lists() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let listen = format!("127.0.0.1:{}", port);
+    let _child = command(&dir)
+        .args(["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--retries", "0"])
+        .args(["serve", "--listen", &listen])
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+
+    let base = format!("http://{}", listen);
+    let client = reqwest::Client::new();
+    let mut v4 = None;
+    for _ in 0..50 {
+        if let Ok(response) = client.get(format!("{}/as/13335/v4.txt", base)).send().await {
+            v4 = Some(response);
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    let v4 = v4.unwrap();
+    assert_eq!(v4.status(), 200);
+    let etag = v4.headers()["etag"].clone();
+    assert_eq!(v4.text().await.unwrap(), "104.16.0.0/13\n1.1.1.0/24\n");
+
+    let cached = client
+        .get(format!("{}/as/13335/v4.txt", base))
+        .header("If-None-Match", etag)
+        .send().await
+        .unwrap();
+    assert_eq!(cached.status(), 304);
+
+    let json: serde_json::Value = client
+        .get(format!("{}/as/13335.json", base))
+        .send().await
+        .unwrap()
+        .json().await
+        .unwrap();
+    assert_eq!(json["ipv6"][0]["prefix"], "2606:4700::/32");
+
+    let nft = client.get(format!("{}/as/13335/nft", base)).send().await.unwrap().text().await.unwrap();
+    assert!(nft.contains("set AS13335_v4 {"));
+    assert!(nft.contains("elements = { 1.1.1.0/24, 104.16.0.0/13 }"));
+
+    let lookup: serde_json::Value = client
+        .get(format!("{}/lookup/1.1.1.1", base))
+        .send().await
+        .unwrap()
+        .json().await
+        .unwrap();
+    assert_eq!(lookup["matches"][0]["prefix"], "1.1.1.0/24");
+    assert_eq!(lookup["matches"][0]["asn"], 13335);
+
+    // --as 以外的ASN不会触发下载
+    let other = client.get(format!("{}/as/15169.json", base)).send().await.unwrap();
+    assert_eq!(other.status(), 403);
+    let other = client.get(format!("{}/as/15169/v4.txt", base)).send().await.unwrap();
+    assert_eq!(other.status(), 403);
+
+    // 预先下载时只抓取一次页面，之后直接使用内存中的数据，不再请求来源网站
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+
+    let output = command(&dir)
+        .args(["--as", "13335", "-i", "2", "--base-url", &server.uri()])
+        .args(["serve", "--listen", &listen, "--refresh-interval", "0"])
+        .output().await
+        .unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--refresh-interval"));
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn serve_ipv6_only_asn() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools_v6_only.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let listen = format!("127.0.0.1:{}", port);
+    let _child = command(&dir)
+        .args(["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--retries", "0"])
+        .args(["serve", "--listen", &listen])
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+
+    let base = format!("http://{}", listen);
+    let client = reqwest::Client::new();
+    let mut v6 = None;
+    for _ in 0..50 {
+        if let Ok(response) = client.get(format!("{}/as/13335/v6.txt", base)).send().await {
+            v6 = Some(response);
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    // 没有IPv4前缀不影响提供IPv6前缀
+    let v6 = v6.unwrap();
+    assert_eq!(v6.status(), 200);
+    assert_eq!(v6.text().await.unwrap(), "2606:4700::/32\n");
+    let v4 = client.get(format!("{}/as/13335/v4.txt", base)).send().await.unwrap();
+    assert_eq!(v4.status(), 200);
+    assert_eq!(v4.text().await.unwrap(), "");
+}
+
+#[tokio::test]
+async fn notify_prefix_changes() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let hooks = MockServer::start().await;
+    Mock::given(method("POST")).respond_with(ResponseTemplate::new(200)).mount(&hooks).await;
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("bgp.tools")).unwrap();
+    fs::write(dir.path().join("bgp.tools/AS13335_v4.txt"), "1.1.1.0/24\n8.8.8.0/24\n").unwrap();
+    let json_sink = format!("{}/hook", hooks.uri());
+    let slack_sink = format!("slack={}/slack", hooks.uri());
+    let output = run(
+        &dir,
+        &[
+            "--as",
+            "13335",
+            "-i",
+            "2",
+            "--base-url",
+            &server.uri(),
+            "--notify",
+            &json_sink,
+            "--notify",
+            &slack_sink,
+        ]
+    ).await;
+    assert!(output.status.success());
+
+    let requests = hooks.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 2);
+    let json = requests.iter().find(|r| r.url.path() == "/hook").unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&json.body).unwrap();
+    assert_eq!(json["changes"][0]["asn"], 13335);
+    assert_eq!(json["changes"][0]["added"], serde_json::json!(["104.16.0.0/13"]));
+    assert_eq!(json["changes"][0]["removed"], serde_json::json!(["8.8.8.0/24"]));
+    let slack = requests.iter().find(|r| r.url.path() == "/slack").unwrap();
+    let slack: serde_json::Value = serde_json::from_slice(&slack.body).unwrap();
+    assert!(slack["text"].as_str().unwrap().contains("+ 104.16.0.0/13\n- 8.8.8.0/24"));
+
+    // 内容没有变化时不再通知
+    run(&dir, &["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--notify", &json_sink]).await;
+    assert_eq!(hooks.received_requests().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn metrics_textfile() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let output = run(
+        &dir,
+        &["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--metrics-file", "as.prom"]
+    ).await;
+
+    assert!(output.status.success());
+    let metrics = read(&dir, "as.prom");
+    assert!(metrics.contains("download_as_cidrs_fetch_attempts_total{source=\"bgp.tools\"} 1\n"));
+    assert!(metrics.contains("download_as_cidrs_http_responses_total{source=\"bgp.tools\",code=\"200\"} 1\n"));
+    assert!(metrics.contains("download_as_cidrs_prefixes{source=\"bgp.tools\",asn=\"13335\",family=\"ipv4\"} 2\n"));
+    // 104.16.0.0/13 和 1.1.1.0/24 共 2^19 + 2^8 个地址
+    assert!(metrics.contains("download_as_cidrs_addresses{source=\"bgp.tools\",asn=\"13335\",family=\"ipv4\"} 524544\n"));
+    assert!(metrics.contains("download_as_cidrs_last_success_timestamp_seconds{source=\"bgp.tools\",asn=\"13335\",family=\"ipv4\"}"));
+    assert!(metrics.contains("# TYPE download_as_cidrs_parse_duration_seconds summary\n"));
+    assert!(metrics.contains("download_as_cidrs_parse_duration_seconds_count{source=\"bgp.tools\"} 1\n"));
+
+    // 下载失败时保留上次成功的时间，用于发现长时间没有更新的ASN
+    let last_success = metrics
+        .lines()
+        .find(|line| line.starts_with("download_as_cidrs_last_success_timestamp_seconds{"))
+        .unwrap()
+        .to_string();
+    let broken = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools_no_table.html"))
+    ).await;
+    let output = run(
+        &dir,
+        &["--as", "13335", "-i", "2", "--base-url", &broken.uri(), "--metrics-file", "as.prom"]
+    ).await;
+    assert!(!output.status.success());
+    assert!(read(&dir, "as.prom").contains(&format!("{}\n", last_success)));
+}
+
+#[tokio::test]
+async fn log_levels_and_json_format() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let args = ["--as", "13335", "-i", "2", "--base-url", &server.uri()];
+
+    // 默认不输出每一行的内容
+    let output = run(&dir, &args).await;
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("抓取到内容"));
+
+    // 改动了结果的处理步骤在默认级别下仍然输出
+    let mut filtered = args.to_vec();
+    filtered.extend(["--max-len-v4", "16"]);
+    let output = run(&dir, &filtered).await;
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("前缀长度 /24 不在 /0~/16 范围内，已去掉：1.1.1.0/24"));
+
+    let mut verbose = args.to_vec();
+    verbose.extend(["-v", "--log-format", "json"]);
+    let output = run(&dir, &verbose).await;
+    assert!(output.status.success());
+    let lines: Vec<serde_json::Value> = String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert!(
+        lines
+            .iter()
+            .any(|l| l["level"] == "DEBUG" && l["fields"]["message"].as_str().unwrap().contains("抓取到内容"))
+    );
+
+    let mut quiet = args.to_vec();
+    quiet.push("-q");
+    let output = run(&dir, &quiet).await;
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+}
+
+#[tokio::test]
+async fn english_messages_and_headers() {
+    let server = serve("/AS13335", ResponseTemplate::new(404)).await;
+    let dir = TempDir::new().unwrap();
+    let output = command(&dir)
+        .args(["--as", "13335", "-i", "1", "--base-url", &server.uri(), "--retries", "0"])
+        .env("LANG", "en_US.UTF-8")
+        .output().await
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("HTTP request failed with status: 404 Not Found"));
+
+    let help = command(&dir).args(["--help", "--lang", "en"]).output().await.unwrap();
+    let help = String::from_utf8_lossy(&help.stdout);
+    assert!(help.contains("ASN to download, digits only without the AS prefix"));
+    assert!(!help.contains("指定(自治系统)ASN"));
+}
+
+#[tokio::test]
+async fn localized_and_machine_csv_headers() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let args = ["--as", "13335", "-i", "2", "--base-url", &server.uri(), "--format", "csv"];
+
+    let mut english = args.to_vec();
+    english.extend(["--lang", "en"]);
+    assert!(run(&dir, &english).await.status.success());
+    assert!(read(&dir, "bgp.tools/AS13335_v4.csv").starts_with("Prefix,Country code,Description\n"));
+
+    let mut machine = args.to_vec();
+    machine.extend(["--csv-header", "machine"]);
+    assert!(run(&dir, &machine).await.status.success());
+    assert!(read(&dir, "bgp.tools/AS13335_v4.csv").starts_with("prefix,country_code,description\n"));
+}
+
+#[tokio::test]
+async fn stats_table_and_json() {
+    let server = serve(
+        "/as/13335",
+        ResponseTemplate::new(200).set_body_string(fixture("bgp_tools.html"))
+    ).await;
+    let dir = TempDir::new().unwrap();
+    let output = command(&dir)
+        .args(["--as", "13335", "-i", "2", "--retries", "0", "--base-url", &server.uri(), "stats", "--json"])
+        .output().await
+        .unwrap();
+
+    assert!(output.status.success());
+    let stats: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let v4 = &stats[0];
+    assert_eq!(v4["family"], 4);
+    assert_eq!(v4["prefixes"], 2);
+    assert_eq!(v4["addresses"], 524544);
+    assert_eq!(v4["slash24"], 2049.0);
+    assert_eq!(v4["by_length"]["13"], 1);
+    assert_eq!(v4["by_country"]["US"]["addresses"], 524288);
+    assert_eq!(v4["by_country"]["AU"]["prefixes"], 1);
+    assert!(!dir.path().join("bgp.tools").exists());
+
+    let output = command(&dir)
+        .args(["--as", "13335", "-i", "2", "-c", "6", "--lang", "en", "--base-url", &server.uri(), "stats"])
+        .output().await
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success());
+    assert!(stdout.contains("/48 equivalents: 65536.00"), "{}", stdout);
+    assert!(stdout.contains("/32 equivalents: 1.0000"), "{}", stdout);
+}